@@ -0,0 +1,87 @@
+#import bevy_pbr::{
+    mesh_functions,
+    view_transformations::position_world_to_clip,
+}
+
+@group(2) @binding(0) var array_texture: texture_2d_array<f32>;
+@group(2) @binding(1) var array_sampler: sampler;
+
+struct TileShading {
+    tint: vec4<f32>,
+    hatching: f32,
+};
+
+@group(2) @binding(2) var<uniform> shading: TileShading;
+
+// layout documented on `ATTRIBUTE_PACKED_VERTEX` in `chunk/packed_mesh.rs`
+struct Vertex {
+    @builtin(instance_index) instance_index: u32,
+    @location(0) packed: u32,
+    @location(1) tile_layer: u32,
+};
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) world_normal: vec3<f32>,
+    @location(1) uv: vec2<f32>,
+    @location(2) shade: f32,
+    @location(3) @interpolate(flat) tile_layer: u32,
+};
+
+// indexed by face id: top, bottom, right, left, front, back
+const NORMALS: array<vec3<f32>, 6> = array<vec3<f32>, 6>(
+    vec3<f32>(0.0, 1.0, 0.0),
+    vec3<f32>(0.0, -1.0, 0.0),
+    vec3<f32>(1.0, 0.0, 0.0),
+    vec3<f32>(-1.0, 0.0, 0.0),
+    vec3<f32>(0.0, 0.0, -1.0),
+    vec3<f32>(0.0, 0.0, 1.0),
+);
+
+@vertex
+fn vertex(vertex: Vertex) -> VertexOutput {
+    var out: VertexOutput;
+
+    let corner = vec3<f32>(
+        f32(vertex.packed & 31u),
+        f32((vertex.packed >> 5u) & 31u),
+        f32((vertex.packed >> 10u) & 31u),
+    );
+    let face = (vertex.packed >> 15u) & 7u;
+    let ao = f32((vertex.packed >> 18u) & 3u) / 3.0;
+
+    let model = mesh_functions::get_model_matrix(vertex.instance_index);
+    let world_position = mesh_functions::mesh_position_local_to_world(model, vec4<f32>(corner - 0.5, 1.0));
+    out.position = position_world_to_clip(world_position.xyz);
+    var normals = NORMALS;
+    out.world_normal = mesh_functions::mesh_normal_local_to_world(normals[face], vertex.instance_index);
+
+    // uvs follow the corner grid, so the texture repeats once per tile on any face size
+    if face < 2u {
+        out.uv = corner.xz;
+    } else if face < 4u {
+        out.uv = vec2<f32>(corner.z, -corner.y);
+    } else {
+        out.uv = vec2<f32>(corner.x, -corner.y);
+    }
+
+    out.shade = 0.5 + 0.5 * ao;
+    out.tile_layer = vertex.tile_layer;
+    return out;
+}
+
+// fixed sun direction, the chunks don't need full pbr lighting
+const LIGHT_DIR: vec3<f32> = vec3<f32>(0.4, 0.8, 0.45);
+
+@fragment
+fn fragment(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = textureSample(array_texture, array_sampler, in.uv, in.tile_layer);
+    let diffuse = max(dot(normalize(in.world_normal), normalize(LIGHT_DIR)), 0.0);
+
+    // diagonal stripes in screen space, used for cut faces
+    let stripe = step(0.5, fract((in.position.x + in.position.y) / 8.0));
+    let hatch = 1.0 - shading.hatching * stripe;
+
+    let rgb = color.rgb * (0.35 + 0.65 * diffuse) * in.shade * hatch * shading.tint.rgb;
+    return vec4<f32>(rgb, color.a);
+}
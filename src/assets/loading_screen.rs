@@ -0,0 +1,162 @@
+use bevy::prelude::*;
+
+use super::LoadingTracker;
+use crate::{
+    dwarf_map::generation::WorldGeneration,
+    prelude::{GameState, LoadingState},
+    simulation::{replay::Replay, SimulationClock},
+};
+
+/// Shows the loading phase and a progress bar while assets load and while a world is generated.
+pub struct LoadingScreenPlugin;
+
+impl Plugin for LoadingScreenPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(OnEnter(GameState::Loading), spawn_loading_screen)
+            .add_systems(OnEnter(GameState::Generating), spawn_loading_screen)
+            .add_systems(
+                Update,
+                update_loading_screen
+                    .run_if(in_state(GameState::Loading).or_else(in_state(GameState::Generating))),
+            )
+            .add_systems(OnExit(GameState::Loading), despawn_loading_screen)
+            .add_systems(OnExit(GameState::Generating), despawn_loading_screen);
+    }
+}
+
+#[derive(Component)]
+struct LoadingScreen;
+
+#[derive(Component)]
+struct LoadingPhaseText;
+
+#[derive(Component)]
+struct LoadingProgressBar;
+
+const BAR_COLOR: Color = Color::rgb(0.8, 0.6, 0.2);
+const ERROR_COLOR: Color = Color::rgb(1.0, 0.4, 0.4);
+
+fn spawn_loading_screen(mut commands: Commands) {
+    commands
+        .spawn((
+            LoadingScreen,
+            NodeBundle {
+                style: Style {
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    row_gap: Val::Px(16.0),
+                    ..default()
+                },
+                background_color: Color::rgb(0.05, 0.05, 0.05).into(),
+                ..default()
+            },
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                LoadingPhaseText,
+                TextBundle::from_section(
+                    "",
+                    TextStyle {
+                        font_size: 24.0,
+                        color: Color::WHITE,
+                        ..default()
+                    },
+                )
+                .with_text_justify(JustifyText::Center),
+            ));
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        width: Val::Px(400.0),
+                        height: Val::Px(16.0),
+                        ..default()
+                    },
+                    background_color: Color::rgb(0.2, 0.2, 0.2).into(),
+                    ..default()
+                })
+                .with_children(|bar| {
+                    bar.spawn((
+                        LoadingProgressBar,
+                        NodeBundle {
+                            style: Style {
+                                width: Val::Percent(0.0),
+                                height: Val::Percent(100.0),
+                                ..default()
+                            },
+                            background_color: BAR_COLOR.into(),
+                            ..default()
+                        },
+                    ));
+                });
+        });
+}
+
+fn update_loading_screen(
+    state: Res<State<LoadingState>>,
+    tracker: Res<LoadingTracker>,
+    generation: Option<Res<WorldGeneration>>,
+    clock: Res<SimulationClock>,
+    replay: Res<Replay>,
+    mut text: Query<&mut Text, With<LoadingPhaseText>>,
+    mut bar: Query<(&mut Style, &mut BackgroundColor), With<LoadingProgressBar>>,
+) {
+    let (message, progress, failed) = match state.get() {
+        LoadingState::LoadingAssets => (
+            format!("Loading tiles {}/{}", tracker.loaded(), tracker.total()),
+            tracker.progress(),
+            false,
+        ),
+        LoadingState::BuildingAtlas => ("Building tile atlas".to_string(), 1.0, false),
+        LoadingState::GeneratingChunks => {
+            let (done, total) = generation.map_or((0, 0), |g| (g.generated(), g.total()));
+            (
+                format!("Generating chunks {done}/{total}"),
+                done as f32 / total.max(1) as f32,
+                false,
+            )
+        }
+        LoadingState::ReplayingSave => {
+            let last = replay.log.last_tick();
+            (
+                format!("Replaying save, tick {}/{last}", clock.tick),
+                clock.tick as f32 / last.max(1) as f32,
+                false,
+            )
+        }
+        LoadingState::Failed => {
+            let mut message = format!(
+                "Loading failed\n\n{}",
+                tracker.error().unwrap_or("Unknown error")
+            );
+            for path in tracker.failed() {
+                message += &format!("\nmissing tile {path}");
+            }
+            (message, 1.0, true)
+        }
+        LoadingState::Done => return,
+    };
+
+    let (text_color, bar_color) = match failed {
+        true => (ERROR_COLOR, ERROR_COLOR),
+        false => (Color::WHITE, BAR_COLOR),
+    };
+    for mut text in text.iter_mut() {
+        if text.sections[0].value != message {
+            text.sections[0].value = message.clone();
+            text.sections[0].style.color = text_color;
+        }
+    }
+    for (mut style, mut background) in bar.iter_mut() {
+        style.width = Val::Percent(progress * 100.0);
+        *background = bar_color.into();
+    }
+}
+
+fn despawn_loading_screen(mut commands: Commands, screens: Query<Entity, With<LoadingScreen>>) {
+    for entity in screens.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
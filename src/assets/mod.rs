@@ -1,10 +1,59 @@
-use bevy::{prelude::*, render::texture::ImageSampler};
+use bevy::{
+    asset::LoadState,
+    prelude::*,
+    render::{
+        render_asset::RenderAssetUsages,
+        render_resource::{Extent3d, TextureDimension, TextureFormat},
+        texture::ImageSampler,
+    },
+};
 
-use crate::prelude::{GameState, LoadingState};
+use crate::{
+    dwarf_map::{
+        prefab::{EmbarkWagon, WAGON_PATH},
+        tile_array::{build_tile_array, TileArrayMaterial},
+        tile_atlas::TileAtlas,
+        MapError,
+    },
+    prelude::{GameState, LoadingState},
+};
+
+mod loading_screen;
 
 #[derive(Reflect, Resource, Default)]
 pub struct LoadingTracker {
     tile_handles: Vec<Handle<Image>>,
+    loaded: usize,
+    /// Tiles that failed to load and were replaced by the missing texture.
+    failed: Vec<String>,
+    /// Why loading stopped, set in [`LoadingState::Failed`].
+    error: Option<String>,
+}
+
+impl LoadingTracker {
+    /// How much of the loading is done, from 0 to 1.
+    pub fn progress(&self) -> f32 {
+        match self.tile_handles.len() {
+            0 => 1.0,
+            total => self.loaded as f32 / total as f32,
+        }
+    }
+
+    pub fn loaded(&self) -> usize {
+        self.loaded
+    }
+
+    pub fn total(&self) -> usize {
+        self.tile_handles.len()
+    }
+
+    pub fn failed(&self) -> &[String] {
+        &self.failed
+    }
+
+    pub fn error(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
 }
 
 pub struct DwarfAssetPlugin;
@@ -18,67 +67,233 @@ impl Plugin for DwarfAssetPlugin {
                 (
                     check_assets_ready.run_if(in_state(LoadingState::LoadingAssets)),
                     build_texture_atlas.run_if(in_state(LoadingState::BuildingAtlas)),
+                    reload_tile_atlas.run_if(resource_exists::<TileAtlas>),
                 ),
-            );
+            )
+            .add_plugins(loading_screen::LoadingScreenPlugin);
     }
 }
 
-fn start_loading(asset_sever: Res<AssetServer>, mut tracker: ResMut<LoadingTracker>) {
+fn start_loading(
+    mut commands: Commands,
+    asset_sever: Res<AssetServer>,
+    mut tracker: ResMut<LoadingTracker>,
+) {
     tracker.tile_handles.push(asset_sever.load("blue.png"));
     tracker.tile_handles.push(asset_sever.load("brown.png"));
     tracker.tile_handles.push(asset_sever.load("green.png"));
     tracker.tile_handles.push(asset_sever.load("orange.png"));
+    commands.insert_resource(EmbarkWagon(asset_sever.load(WAGON_PATH)));
 }
 
+/// Waits for all tiles, tiles that fail to load are replaced by a checkerboard,
+/// so a missing png shows up in game instead of stopping the loading.
+/// The [`EmbarkWagon`] is waited for as well, without it the dwarves start on their own.
 fn check_assets_ready(
     server: Res<AssetServer>,
-    tracker: Res<LoadingTracker>,
+    mut tracker: ResMut<LoadingTracker>,
+    mut images: ResMut<Assets<Image>>,
+    wagon: Res<EmbarkWagon>,
     mut load_state: ResMut<NextState<LoadingState>>,
 ) {
-    use bevy::asset::LoadState;
+    let tracker = tracker.as_mut();
+    let mut loaded = 0;
 
-    let mut done = true;
-
-    for asset in &tracker.tile_handles {
-        match server.load_state(asset.id()) {
-            LoadState::Loading => {}
-            LoadState::Failed => done = false,
-            _ => {}
+    for handle in tracker.tile_handles.iter_mut() {
+        match server.load_state(handle.id()) {
+            // the missing texture isn't known to the server
+            LoadState::NotLoaded if images.contains(handle.id()) => loaded += 1,
+            LoadState::NotLoaded | LoadState::Loading => {}
+            LoadState::Loaded => loaded += 1,
+            LoadState::Failed => {
+                let path = handle
+                    .path()
+                    .map_or_else(|| format!("{:?}", handle.id()), |p| p.to_string());
+                error!("Failed to load tile {path}, using the missing texture instead");
+                tracker.failed.push(path);
+                *handle = images.add(missing_texture());
+                loaded += 1;
+            }
         };
     }
+    tracker.loaded = loaded;
 
-    if done {
+    let wagon = server.load_state(wagon.0.id());
+    if loaded == tracker.tile_handles.len()
+        && matches!(wagon, LoadState::Loaded | LoadState::Failed)
+    {
+        if matches!(wagon, LoadState::Failed) {
+            error!("Failed to load the embark wagon {WAGON_PATH}");
+        }
         load_state.set(LoadingState::BuildingAtlas)
     }
 }
 
+/// Magenta and black checkerboard, the size of a tile.
+fn missing_texture() -> Image {
+    const SIZE: u32 = 16;
+    let data = (0..SIZE * SIZE)
+        .flat_map(|i| match (i % SIZE / 4 + i / SIZE / 4) % 2 {
+            0 => [255, 0, 255, 255],
+            _ => [0, 0, 0, 255],
+        })
+        .collect();
+    Image::new(
+        Extent3d {
+            width: SIZE,
+            height: SIZE,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        data,
+        TextureFormat::Rgba8UnormSrgb,
+        // the atlas is built from the pixels on the cpu
+        RenderAssetUsages::all(),
+    )
+}
+
 fn build_texture_atlas(
     mut commands: Commands,
     mut game_state: ResMut<NextState<GameState>>,
     mut loading: ResMut<NextState<LoadingState>>,
-    tracker: Res<LoadingTracker>,
+    mut tracker: ResMut<LoadingTracker>,
     mut textures: ResMut<Assets<Image>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
+    mut array_materials: ResMut<Assets<TileArrayMaterial>>,
 ) {
-    let mut builder = TextureAtlasBuilder::default().padding(UVec2::splat(5));
-    for img in &tracker.tile_handles {
-        let id = img.id();
-        builder.add_texture(Some(id), textures.get(id).unwrap())
+    match build_tile_atlas(
+        &tracker,
+        &mut textures,
+        &mut materials,
+        &mut array_materials,
+    ) {
+        Ok(tiles) => {
+            commands.insert_resource(tiles);
+            game_state.set(GameState::MainMenu);
+            loading.set(LoadingState::Done);
+        }
+        Err(err) => {
+            error!("{err}");
+            tracker.error = Some(err.to_string());
+            loading.set(LoadingState::Failed);
+        }
     }
+}
 
-    let (layout, mut text) = builder.finish().unwrap();
-    text.sampler = ImageSampler::nearest();
-    let hnd = textures.add(text);
-    let tiles = crate::dwarf_map::tile_atlas::TileAtlas {
+fn build_tile_atlas(
+    tracker: &LoadingTracker,
+    textures: &mut Assets<Image>,
+    materials: &mut Assets<StandardMaterial>,
+    array_materials: &mut Assets<TileArrayMaterial>,
+) -> Result<TileAtlas, MapError> {
+    let (layout, image, array) = pack_tiles(tracker, textures)?;
+    let hnd = textures.add(image);
+    let array_hnd = textures.add(array);
+
+    Ok(TileAtlas {
         image: hnd.clone(),
         material: materials.add(StandardMaterial {
             base_color_texture: Some(hnd.clone()),
             ..default()
         }),
         layout,
+        array_image: array_hnd.clone(),
+        array_material: array_materials.add(TileArrayMaterial {
+            array_texture: array_hnd,
+            shading: default(),
+        }),
+    })
+}
+
+/// Packs the tiles into the atlas image and the array image with the same layout.
+fn pack_tiles(
+    tracker: &LoadingTracker,
+    textures: &Assets<Image>,
+) -> Result<(TextureAtlasLayout, Image, Image), MapError> {
+    let mut builder = TextureAtlasBuilder::default().padding(UVec2::splat(5));
+    for img in &tracker.tile_handles {
+        let id = img.id();
+        let image = textures
+            .get(id)
+            .ok_or_else(|| MapError::Atlas(format!("tile {:?} is not loaded", img.path())))?;
+        builder.add_texture(Some(id), image);
+    }
+
+    let (layout, mut text) = builder
+        .finish()
+        .map_err(|err| MapError::Atlas(err.to_string()))?;
+    text.sampler = ImageSampler::nearest();
+
+    // array layers follow the atlas layout, so a tile index is valid for both
+    let array = (0..layout.textures.len())
+        .map(|i| {
+            tracker
+                .tile_handles
+                .iter()
+                .find(|h| layout.get_texture_index(h.id()) == Some(i))
+                .and_then(|handle| textures.get(handle.id()))
+                .ok_or_else(|| MapError::Atlas(format!("no tile for atlas index {i}")))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok((layout, text, build_tile_array(array)?))
+}
+
+/// Repacks the [`TileAtlas`] when a tile png changes on disk, the game keeps running.
+/// The new images replace the old ones behind the same handles, so every material keeps working.
+/// The layout only changes if a tile changed its size, [`TileAtlas`] is then marked as changed
+/// so chunks and items get remeshed.
+///
+/// Bevy only watches the asset folder with its `file_watcher` feature, run with
+/// `cargo run --features hot_reload` to turn it on.
+fn reload_tile_atlas(
+    mut events: EventReader<AssetEvent<Image>>,
+    tracker: Res<LoadingTracker>,
+    mut atlas: ResMut<TileAtlas>,
+    mut textures: ResMut<Assets<Image>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut array_materials: ResMut<Assets<TileArrayMaterial>>,
+) {
+    let modified = events.read().any(|event| match event {
+        AssetEvent::Modified { id } => tracker.tile_handles.iter().any(|h| h.id() == *id),
+        _ => false,
+    });
+    if !modified {
+        return;
+    }
+
+    let (layout, image, array) = match pack_tiles(&tracker, &textures) {
+        Ok(packed) => packed,
+        Err(err) => {
+            error!("Failed to rebuild the tile atlas, keeping the old one: {err}");
+            return;
+        }
     };
+    textures.insert(atlas.image.id(), image);
+    textures.insert(atlas.array_image.id(), array);
+
+    // materials don't notice when only their texture changes, this includes the faded layer materials
+    let std_ids: Vec<_> = materials
+        .iter()
+        .filter(|(_, m)| m.base_color_texture.as_ref() == Some(&atlas.image))
+        .map(|(id, _)| id)
+        .collect();
+    for id in std_ids {
+        materials.get_mut(id);
+    }
+    let array_ids: Vec<_> = array_materials
+        .iter()
+        .filter(|(_, m)| m.array_texture == atlas.array_image)
+        .map(|(id, _)| id)
+        .collect();
+    for id in array_ids {
+        array_materials.get_mut(id);
+    }
 
-    commands.insert_resource(tiles);
-    game_state.set(GameState::Playing);
-    loading.set(LoadingState::Done);
+    if atlas.layout.size != layout.size || atlas.layout.textures != layout.textures {
+        info!("Tile atlas layout changed, remeshing");
+        atlas.layout = layout;
+    } else {
+        info!("Reloaded tile textures");
+    }
 }
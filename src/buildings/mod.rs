@@ -0,0 +1,521 @@
+use bevy::{
+    prelude::*,
+    utils::{HashMap, HashSet},
+};
+
+use crate::{
+    dwarf_map::{
+        chunk::{ChunkCache, ChunkData, MapCommands, Tile},
+        cursor::TileCursor,
+        tile_to_world, TilePos,
+    },
+    items::{Item, ItemKind, Reserved},
+    prelude::*,
+    settings::{Action, Settings},
+    simulation::{
+        replay::{PlayerCommand, PlayerCommands, TickCommands},
+        Simulation, SimulationSet,
+    },
+};
+
+/// Walls, floors, doors and workshops, placed as blueprints on the tile grid.
+pub struct BuildingPlugin;
+
+impl Plugin for BuildingPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<BuildPlacement>()
+            .register_type::<BuildPlacement>()
+            .register_type::<Blueprint>()
+            .register_type::<Building>()
+            .add_systems(OnExit(GameState::Loading), init_building_assets)
+            .add_systems(
+                Update,
+                (select_building, place_blueprint, draw_blueprints)
+                    .chain()
+                    .run_if(in_state(GameState::Playing)),
+            )
+            .add_systems(Update, attach_building_visuals.run_if(in_world))
+            .add_systems(
+                Simulation,
+                (
+                    apply_blueprint_commands,
+                    release_reservations,
+                    reserve_materials,
+                    complete_blueprints,
+                )
+                    .chain()
+                    .in_set(SimulationSet::Update)
+                    .run_if(in_simulation),
+            );
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Reflect)]
+pub enum BuildingKind {
+    /// Becomes a solid tile, meshed like any natural wall.
+    Wall,
+    /// Fills the tile below, so there is a floor to stand on.
+    Floor,
+    Door,
+    Workshop,
+}
+
+impl BuildingKind {
+    pub const ALL: [BuildingKind; 4] = [
+        BuildingKind::Wall,
+        BuildingKind::Floor,
+        BuildingKind::Door,
+        BuildingKind::Workshop,
+    ];
+
+    /// Size on the x and z axis, in tiles.
+    pub fn size(&self) -> UVec2 {
+        match self {
+            BuildingKind::Workshop => UVec2::splat(3),
+            _ => UVec2::ONE,
+        }
+    }
+
+    /// Height and color of the kinds that stand on the map as their own entity,
+    /// walls and floors become tiles instead.
+    fn model(&self) -> Option<(f32, Color)> {
+        match self {
+            BuildingKind::Door => Some((1.0, Color::rgb(0.5, 0.3, 0.15))),
+            BuildingKind::Workshop => Some((0.6, Color::rgb(0.6, 0.55, 0.45))),
+            BuildingKind::Wall | BuildingKind::Floor => None,
+        }
+    }
+
+    /// Number of items that get used up.
+    pub fn cost(&self) -> usize {
+        match self {
+            BuildingKind::Workshop => 3,
+            _ => 1,
+        }
+    }
+
+    /// Items that can be used as material.
+    pub fn accepts(&self, kind: ItemKind) -> bool {
+        match self {
+            BuildingKind::Wall | BuildingKind::Floor => {
+                matches!(kind, ItemKind::Boulder | ItemKind::Bar)
+            }
+            BuildingKind::Door | BuildingKind::Workshop => {
+                matches!(kind, ItemKind::Boulder | ItemKind::Log)
+            }
+        }
+    }
+
+    /// Tiles covered when placed with the cursor on `center`.
+    pub fn footprint(&self, center: UVec3) -> Footprint {
+        let size = self.size();
+        let half = size / 2;
+        let min = UVec3::new(
+            center.x.saturating_sub(half.x),
+            center.y,
+            center.z.saturating_sub(half.y),
+        );
+        Footprint { min, size }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Reflect)]
+pub struct Footprint {
+    pub min: UVec3,
+    pub size: UVec2,
+}
+
+impl Footprint {
+    pub fn tiles(&self) -> impl Iterator<Item = UVec3> {
+        let Footprint { min, size } = *self;
+        (0..size.x).flat_map(move |x| (0..size.y).map(move |z| min + UVec3::new(x, 0, z)))
+    }
+
+    /// World position of the center, on the floor of the layer.
+    pub fn floor_center(&self) -> Vec3 {
+        let max = self.min + UVec3::new(self.size.x, 1, self.size.y) - UVec3::ONE;
+        (tile_to_world(self.min) + tile_to_world(max)) / 2.0 - Vec3::Y * 0.5
+    }
+}
+
+/// Building that is waiting for materials and work.
+#[derive(Debug, Component, Clone, Reflect)]
+#[reflect(Component)]
+pub struct Blueprint {
+    pub kind: BuildingKind,
+    pub footprint: Footprint,
+    /// Items claimed with [`Reserved`], they get used up when the building is finished.
+    pub materials: Vec<Entity>,
+    /// Seconds of work left once all materials are reserved.
+    pub work_left: f32,
+    /// Dwarf that took the construction job.
+    pub worker: Option<Entity>,
+}
+
+impl Blueprint {
+    pub fn new(kind: BuildingKind, footprint: Footprint) -> Self {
+        Self {
+            kind,
+            footprint,
+            materials: vec![],
+            work_left: BUILD_TIME * kind.cost() as f32,
+            worker: None,
+        }
+    }
+
+    pub fn has_materials(&self) -> bool {
+        self.materials.len() >= self.kind.cost()
+    }
+
+    /// Whether the construction job can be taken.
+    pub fn is_ready(&self) -> bool {
+        self.has_materials() && self.work_left > 0.0
+    }
+}
+
+/// Seconds of work per used item.
+const BUILD_TIME: f32 = 2.0;
+
+/// A finished building that is not part of the tiles, like doors and workshops.
+#[derive(Debug, Component, Clone, Copy, Reflect)]
+#[reflect(Component)]
+pub struct Building {
+    pub kind: BuildingKind,
+    pub footprint: Footprint,
+    /// Material of the first item it was built from.
+    pub material: usize,
+}
+
+/// What the player is about to place, if anything.
+#[derive(Debug, Resource, Reflect, Default)]
+pub struct BuildPlacement {
+    pub selected: Option<BuildingKind>,
+}
+
+/// Reasons a building can't be placed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlacementError {
+    OutOfMap,
+    NoFloor,
+    Blocked,
+    Occupied,
+}
+
+/// Checks that every tile of the footprint is empty and stands on a solid tile.
+/// Floors are the exception, they need the tile below to be empty, because that is the one they fill.
+pub fn validate_footprint(
+    kind: BuildingKind,
+    footprint: Footprint,
+    cache: &ChunkCache,
+    chunks: &Query<&ChunkData>,
+    occupied: &HashSet<UVec3>,
+) -> Result<(), PlacementError> {
+    for (tile, here) in cache.tiles(chunks, footprint.tiles()) {
+        let below = tile.y.checked_sub(1).ok_or(PlacementError::OutOfMap)?;
+        let here = here.map_err(|_| PlacementError::OutOfMap)?;
+        let floor = cache
+            .get_tile(chunks, UVec3::new(tile.x, below, tile.z))
+            .map_err(|_| PlacementError::OutOfMap)?;
+
+        if here.is_solid() {
+            return Err(PlacementError::Blocked);
+        }
+        match (kind, floor.is_solid()) {
+            (BuildingKind::Floor, true) => return Err(PlacementError::Blocked),
+            (BuildingKind::Floor, false) => {}
+            (_, false) => return Err(PlacementError::NoFloor),
+            (_, true) => {}
+        }
+        if occupied.contains(&tile) {
+            return Err(PlacementError::Occupied);
+        }
+    }
+    Ok(())
+}
+
+fn occupied_tiles<'a>(
+    blueprints: impl Iterator<Item = &'a Blueprint>,
+    buildings: impl Iterator<Item = &'a Building>,
+) -> HashSet<UVec3> {
+    blueprints
+        .map(|b| b.footprint)
+        .chain(buildings.map(|b| b.footprint))
+        .flat_map(|f| f.tiles())
+        .collect()
+}
+
+/// `B` cycles through the buildings, the cancel key stops placing.
+fn select_building(
+    keys: Res<ButtonInput<KeyCode>>,
+    settings: Res<Settings>,
+    mut placement: ResMut<BuildPlacement>,
+) {
+    if settings.keys.just_pressed(&keys, Action::Cancel) {
+        placement.selected = None;
+    }
+    if settings.keys.just_pressed(&keys, Action::CycleBuilding) {
+        let next = match placement.selected {
+            None => Some(0),
+            Some(kind) => BuildingKind::ALL
+                .iter()
+                .position(|k| *k == kind)
+                .map(|i| i + 1)
+                .filter(|i| *i < BuildingKind::ALL.len()),
+        };
+        placement.selected = next.map(|i| BuildingKind::ALL[i]);
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn place_blueprint(
+    mut player_commands: ResMut<PlayerCommands>,
+    mouse: Res<ButtonInput<MouseButton>>,
+    cursor: Res<TileCursor>,
+    placement: Res<BuildPlacement>,
+    cache: Res<ChunkCache>,
+    chunks: Query<&ChunkData>,
+    blueprints: Query<&Blueprint>,
+    buildings: Query<&Building>,
+    mut gizmos: Gizmos,
+) {
+    let (Some(kind), Some(center)) = (placement.selected, cursor.hovered) else {
+        return;
+    };
+
+    let footprint = kind.footprint(center);
+    let occupied = occupied_tiles(blueprints.iter(), buildings.iter());
+    let valid = validate_footprint(kind, footprint, &cache, &chunks, &occupied);
+
+    // ghost preview
+    let color = match valid {
+        Ok(()) => Color::rgba(0.2, 0.9, 0.3, 0.8),
+        Err(_) => Color::rgba(0.9, 0.2, 0.2, 0.8),
+    };
+    for tile in footprint.tiles() {
+        gizmos.cuboid(
+            Transform::from_translation(tile_to_world(tile)).with_scale(Vec3::splat(0.9)),
+            color,
+        );
+    }
+
+    if !mouse.just_pressed(MouseButton::Left) {
+        return;
+    }
+    match valid {
+        Ok(()) => player_commands.send(PlayerCommand::PlaceBlueprint { kind, center }),
+        Err(err) => info!("Can't place {kind:?} at {center}: {err:?}"),
+    }
+}
+
+/// Validated again, the map may have changed since the command was sent.
+fn apply_blueprint_commands(
+    mut commands: Commands,
+    tick_commands: Res<TickCommands>,
+    cache: Res<ChunkCache>,
+    chunks: Query<&ChunkData>,
+    blueprints: Query<&Blueprint>,
+    buildings: Query<&Building>,
+) {
+    let mut occupied = occupied_tiles(blueprints.iter(), buildings.iter());
+
+    for command in tick_commands.iter() {
+        let PlayerCommand::PlaceBlueprint { kind, center } = *command else {
+            continue;
+        };
+        let footprint = kind.footprint(center);
+        match validate_footprint(kind, footprint, &cache, &chunks, &occupied) {
+            Ok(()) => {
+                occupied.extend(footprint.tiles());
+                commands.spawn((
+                    Blueprint::new(kind, footprint),
+                    TilePos(footprint.min),
+                    Name::new(format!("{kind:?} blueprint")),
+                ));
+            }
+            Err(err) => info!("Can't place {kind:?} at {center}: {err:?}"),
+        }
+    }
+}
+
+fn draw_blueprints(blueprints: Query<&Blueprint>, mut gizmos: Gizmos) {
+    for blueprint in blueprints.iter() {
+        let color = match blueprint.has_materials() {
+            true => Color::rgba(0.3, 0.6, 1.0, 0.8),
+            false => Color::rgba(0.3, 0.6, 1.0, 0.3),
+        };
+        for tile in blueprint.footprint.tiles() {
+            gizmos.cuboid(
+                Transform::from_translation(tile_to_world(tile)).with_scale(Vec3::splat(0.8)),
+                color,
+            );
+        }
+    }
+}
+
+/// Frees items whose blueprint is gone.
+fn release_reservations(
+    mut commands: Commands,
+    items: Query<(Entity, &Reserved), With<Item>>,
+    blueprints: Query<(), With<Blueprint>>,
+) {
+    for (entity, reserved) in items.iter() {
+        if !blueprints.contains(reserved.0) {
+            commands.entity(entity).remove::<Reserved>();
+        }
+    }
+}
+
+/// Claims free items for blueprints that are still missing materials, closest ones first.
+fn reserve_materials(
+    mut commands: Commands,
+    mut blueprints: Query<(Entity, &mut Blueprint)>,
+    items: Query<(Entity, &Item, &TilePos), Without<Reserved>>,
+) {
+    let mut claimed = HashSet::new();
+
+    // blueprints compete for the closest items, they never overlap so their corner gives a stable order
+    let mut order: Vec<_> = blueprints
+        .iter()
+        .map(|(e, b)| (b.footprint.min.to_array(), e))
+        .collect();
+    order.sort_unstable();
+
+    for (_, entity) in order {
+        let Ok((_, mut blueprint)) = blueprints.get_mut(entity) else {
+            continue;
+        };
+        if blueprint.has_materials() {
+            continue;
+        }
+        let kind = blueprint.kind;
+        let origin = blueprint.footprint.min.as_ivec3();
+
+        let mut free: Vec<_> = items
+            .iter()
+            .filter(|(e, item, _)| kind.accepts(item.kind) && !claimed.contains(e))
+            .map(|(e, item, pos)| {
+                let key = (
+                    origin.distance_squared(pos.as_ivec3()),
+                    pos.to_array(),
+                    item.kind as u8,
+                    item.material,
+                );
+                (key, e)
+            })
+            .collect();
+        // entity ids differ between replays, items with the same key are interchangeable
+        free.sort_unstable_by_key(|(key, _)| *key);
+
+        let missing = kind.cost() - blueprint.materials.len();
+        for (_, item) in free.into_iter().take(missing) {
+            claimed.insert(item);
+            commands.entity(item).insert(Reserved(entity));
+            blueprint.materials.push(item);
+        }
+    }
+}
+
+fn complete_blueprints(
+    mut map: MapCommands,
+    blueprints: Query<(Entity, &Blueprint)>,
+    items: Query<&Item>,
+) {
+    for (entity, blueprint) in blueprints.iter() {
+        if !blueprint.has_materials() || blueprint.work_left > 0.0 {
+            continue;
+        }
+        let Some(material) = blueprint
+            .materials
+            .iter()
+            .find_map(|e| items.get(*e).ok())
+            .map(|item| item.material)
+        else {
+            continue;
+        };
+
+        let kind = blueprint.kind;
+        let footprint = blueprint.footprint;
+        match kind {
+            BuildingKind::Wall => {
+                for tile in footprint.tiles() {
+                    match map.get_tile(tile) {
+                        Ok(mut commands) => commands.set(Tile::solid(material)),
+                        Err(err) => warn!("Building {kind:?}: {err}"),
+                    }
+                }
+            }
+            BuildingKind::Floor => {
+                for tile in footprint.tiles() {
+                    match map.get_tile(tile - UVec3::Y) {
+                        Ok(mut commands) => commands.set(Tile::solid(material)),
+                        Err(err) => warn!("Building {kind:?}: {err}"),
+                    }
+                }
+            }
+            BuildingKind::Door | BuildingKind::Workshop => {
+                map.commands().spawn((
+                    Building {
+                        kind,
+                        footprint,
+                        material,
+                    },
+                    TilePos(footprint.min),
+                    Name::new(format!("{kind:?}")),
+                ));
+            }
+        }
+
+        let mut commands = map.commands();
+        for item in &blueprint.materials {
+            commands.entity(*item).despawn_recursive();
+        }
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+/// Mesh and material for every kind with a [`BuildingKind::model`], shared by all buildings of the kind.
+#[derive(Resource)]
+struct BuildingAssets {
+    models: HashMap<BuildingKind, (Handle<Mesh>, Handle<StandardMaterial>)>,
+}
+
+fn init_building_assets(
+    mut commands: Commands,
+    mut mesh_assets: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    let models = BuildingKind::ALL
+        .into_iter()
+        .filter_map(|kind| {
+            let (height, color) = kind.model()?;
+            let size = kind.size().as_vec2();
+            let mesh = mesh_assets.add(Cuboid::new(size.x, height, size.y));
+            Some((kind, (mesh, materials.add(color))))
+        })
+        .collect();
+
+    commands.insert_resource(BuildingAssets { models });
+}
+
+/// Buildings are finished by the simulation, they get drawn once the frame catches up.
+fn attach_building_visuals(
+    mut commands: Commands,
+    assets: Res<BuildingAssets>,
+    buildings: Query<(Entity, &Building), Added<Building>>,
+) {
+    for (entity, building) in buildings.iter() {
+        let (Some((height, _)), Some((mesh, material))) =
+            (building.kind.model(), assets.models.get(&building.kind))
+        else {
+            continue;
+        };
+        commands.entity(entity).insert(PbrBundle {
+            mesh: mesh.clone(),
+            material: material.clone(),
+            transform: Transform::from_translation(
+                building.footprint.floor_center() + Vec3::Y * height / 2.0,
+            ),
+            ..default()
+        });
+    }
+}
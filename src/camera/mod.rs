@@ -0,0 +1,127 @@
+use bevy::prelude::*;
+use smooth_bevy_cameras::{controllers::fps::FpsCameraController, LookTransform};
+
+use crate::{
+    dwarf_map::chunk::ChunkCache,
+    prelude::*,
+    settings::{Action, Settings},
+};
+
+mod orbit;
+mod top_down;
+
+pub use orbit::OrbitView;
+pub use top_down::TopDownView;
+
+/// Handles the different ways of looking at the map and switching between them.
+pub struct DwarfCameraPlugin;
+
+impl Plugin for DwarfCameraPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<CameraMode>()
+            .register_type::<CameraMode>()
+            .add_plugins(top_down::TopDownCameraPlugin)
+            .add_plugins(orbit::OrbitCameraPlugin)
+            .add_systems(
+                Update,
+                (toggle_camera_mode, apply_camera_mode)
+                    .chain()
+                    .run_if(in_world),
+            );
+    }
+}
+
+/// The camera used to look at the map.
+#[derive(Debug, Component)]
+pub struct MainCamera;
+
+#[derive(Debug, Resource, Reflect, Default, Clone, Copy, PartialEq, Eq)]
+pub enum CameraMode {
+    /// Free flying perspective camera.
+    #[default]
+    Fps,
+    /// Orthographic camera looking straight down on the current layer.
+    TopDown,
+    /// RTS style camera orbiting a point on the current layer.
+    Orbit,
+}
+
+impl CameraMode {
+    pub fn next(self) -> Self {
+        match self {
+            CameraMode::Fps => CameraMode::TopDown,
+            CameraMode::TopDown => CameraMode::Orbit,
+            CameraMode::Orbit => CameraMode::Fps,
+        }
+    }
+}
+
+/// Keeps a focus point (x and z in world space) above the loaded chunks.
+pub fn clamp_to_map(focus: Vec2, cache: &ChunkCache) -> Vec2 {
+    match cache.tile_bounds() {
+        Some((min, max)) => focus.clamp(min.xz().as_vec2(), max.xz().as_vec2()),
+        None => focus,
+    }
+}
+
+fn toggle_camera_mode(
+    keys: Res<ButtonInput<KeyCode>>,
+    settings: Res<Settings>,
+    mut mode: ResMut<CameraMode>,
+) {
+    if settings.keys.just_pressed(&keys, Action::ToggleCamera) {
+        *mode = mode.next();
+    }
+}
+
+/// Last fps camera position, restored when switching back to it.
+#[derive(Default)]
+struct SavedFpsView(Option<LookTransform>);
+
+fn apply_camera_mode(
+    mode: Res<CameraMode>,
+    mut saved: Local<SavedFpsView>,
+    mut top_down: ResMut<TopDownView>,
+    mut orbit: ResMut<OrbitView>,
+    mut cameras: Query<
+        (
+            &mut Projection,
+            &mut LookTransform,
+            &mut FpsCameraController,
+        ),
+        With<MainCamera>,
+    >,
+) {
+    if !mode.is_changed() {
+        return;
+    }
+
+    for (mut projection, mut look, mut fps) in cameras.iter_mut() {
+        fps.enabled = *mode == CameraMode::Fps;
+
+        match *mode {
+            CameraMode::Fps => {
+                *projection = Projection::Perspective(default());
+                if let Some(old) = saved.0.take() {
+                    *look = old;
+                }
+            }
+            CameraMode::TopDown => {
+                *projection = Projection::Orthographic(top_down::projection());
+                if saved.0.is_none() {
+                    saved.0 = Some(*look);
+                    // keep looking at the same spot of the map
+                    top_down.focus = look.target.xz();
+                }
+            }
+            CameraMode::Orbit => {
+                *projection = Projection::Perspective(default());
+                if saved.0.is_none() {
+                    saved.0 = Some(*look);
+                }
+                // continue where the previous view was looking at
+                orbit.focus = look.target.xz();
+            }
+        }
+    }
+}
@@ -0,0 +1,171 @@
+use bevy::{
+    input::mouse::{MouseMotion, MouseScrollUnit, MouseWheel},
+    prelude::*,
+    window::PrimaryWindow,
+};
+use smooth_bevy_cameras::LookTransform;
+
+use super::{clamp_to_map, CameraMode, MainCamera};
+use crate::{
+    dwarf_map::{chunk::ChunkCache, tile_to_world, CurrentMapLayer},
+    prelude::*,
+    settings::{Action, Settings},
+};
+
+/// RTS style camera orbiting a focus point on the current layer.
+pub struct OrbitCameraPlugin;
+
+impl Plugin for OrbitCameraPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<OrbitView>()
+            .register_type::<OrbitView>()
+            .add_systems(
+                Update,
+                (pan_orbit_zoom, follow_focus)
+                    .chain()
+                    .after(super::apply_camera_mode)
+                    .run_if(in_world)
+                    .run_if(resource_equals(CameraMode::Orbit)),
+            );
+    }
+}
+
+/// Distance from the window border in pixels that starts edge scrolling.
+const EDGE_SCROLL_MARGIN: f32 = 8.0;
+
+#[derive(Debug, Resource, Reflect)]
+pub struct OrbitView {
+    /// Point the camera orbits around, x and z in world space.
+    /// The height follows the [`CurrentMapLayer`].
+    pub focus: Vec2,
+    /// Rotation around the focus, in radians.
+    pub yaw: f32,
+    /// Angle above the horizon, in radians.
+    pub pitch: f32,
+    pub distance: f32,
+    /// Tiles per second at a distance of 20 tiles.
+    pub pan_speed: f32,
+    /// Radians per pixel of mouse movement.
+    pub rotate_sensitivity: f32,
+    pub edge_scroll: bool,
+}
+
+impl Default for OrbitView {
+    fn default() -> Self {
+        Self {
+            focus: Vec2::new(8.0, 8.0),
+            yaw: 0.0,
+            pitch: 0.9,
+            distance: 20.0,
+            pan_speed: 20.0,
+            rotate_sensitivity: 0.005,
+            edge_scroll: true,
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn pan_orbit_zoom(
+    time: Res<Time>,
+    keys: Res<ButtonInput<KeyCode>>,
+    settings: Res<Settings>,
+    mouse: Res<ButtonInput<MouseButton>>,
+    mut motion: EventReader<MouseMotion>,
+    mut wheel: EventReader<MouseWheel>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    cache: Res<ChunkCache>,
+    mut view: ResMut<OrbitView>,
+) {
+    // panning, relative to the direction the camera is facing
+    let mut dir = Vec2::ZERO;
+    let bindings = &settings.keys;
+    if keys.any_pressed([bindings.key(Action::PanUp), KeyCode::ArrowUp]) {
+        dir.y += 1.0;
+    }
+    if keys.any_pressed([bindings.key(Action::PanDown), KeyCode::ArrowDown]) {
+        dir.y -= 1.0;
+    }
+    if keys.any_pressed([bindings.key(Action::PanLeft), KeyCode::ArrowLeft]) {
+        dir.x -= 1.0;
+    }
+    if keys.any_pressed([bindings.key(Action::PanRight), KeyCode::ArrowRight]) {
+        dir.x += 1.0;
+    }
+
+    if view.edge_scroll {
+        if let Some(cursor) = windows.get_single().ok().and_then(|w| {
+            w.cursor_position()
+                .map(|pos| (pos, Vec2::new(w.width(), w.height())))
+        }) {
+            let (pos, size) = cursor;
+            if pos.x < EDGE_SCROLL_MARGIN {
+                dir.x -= 1.0;
+            }
+            if pos.x > size.x - EDGE_SCROLL_MARGIN {
+                dir.x += 1.0;
+            }
+            if pos.y < EDGE_SCROLL_MARGIN {
+                dir.y += 1.0;
+            }
+            if pos.y > size.y - EDGE_SCROLL_MARGIN {
+                dir.y -= 1.0;
+            }
+        }
+    }
+
+    if dir != Vec2::ZERO {
+        let forward = Vec2::new(-view.yaw.sin(), -view.yaw.cos());
+        let right = Vec2::new(-forward.y, forward.x);
+        let speed = view.pan_speed * view.distance / 20.0;
+        let delta = (right * dir.x + forward * dir.y).normalize() * speed * time.delta_seconds();
+        view.focus = clamp_to_map(view.focus + delta, &cache);
+    }
+
+    // rotating, with Q/E or by dragging with the right mouse button
+    let mut rotation = Vec2::ZERO;
+    if bindings.pressed(&keys, Action::RotateLeft) {
+        rotation.x -= 1.0;
+    }
+    if bindings.pressed(&keys, Action::RotateRight) {
+        rotation.x += 1.0;
+    }
+    rotation *= time.delta_seconds() * 2.0;
+
+    let dragging = mouse.pressed(MouseButton::Right);
+    for event in motion.read() {
+        if dragging {
+            rotation += event.delta * view.rotate_sensitivity;
+        }
+    }
+    view.yaw = (view.yaw - rotation.x).rem_euclid(std::f32::consts::TAU);
+    view.pitch = (view.pitch + rotation.y).clamp(0.1, 1.5);
+
+    for event in wheel.read() {
+        let lines = match event.unit {
+            MouseScrollUnit::Line => event.y,
+            MouseScrollUnit::Pixel => event.y / 32.0,
+        };
+        view.distance = (view.distance * (1.0 - lines * 0.1)).clamp(4.0, 120.0);
+    }
+}
+
+fn follow_focus(
+    view: Res<OrbitView>,
+    layer: Res<CurrentMapLayer>,
+    mut cameras: Query<&mut LookTransform, With<MainCamera>>,
+) {
+    let height = tile_to_world(UVec3::new(0, layer.0 as u32, 0)).y;
+    let target = Vec3::new(view.focus.x, height, view.focus.y);
+    let offset = Vec3::new(
+        view.yaw.sin() * view.pitch.cos(),
+        view.pitch.sin(),
+        view.yaw.cos() * view.pitch.cos(),
+    ) * view.distance;
+
+    for mut look in cameras.iter_mut() {
+        let eye = target + offset;
+        if look.eye != eye || look.target != target {
+            *look = LookTransform::new(eye, target, Vec3::Y);
+        }
+    }
+}
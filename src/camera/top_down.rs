@@ -0,0 +1,122 @@
+use bevy::{
+    input::mouse::{MouseScrollUnit, MouseWheel},
+    prelude::*,
+    render::camera::ScalingMode,
+};
+use smooth_bevy_cameras::LookTransform;
+
+use super::{clamp_to_map, CameraMode, MainCamera};
+use crate::{
+    dwarf_map::{chunk::ChunkCache, tile_to_world, CurrentMapLayer},
+    prelude::*,
+    settings::{Action, Settings},
+};
+
+/// Classic top down view of the current layer.
+pub struct TopDownCameraPlugin;
+
+impl Plugin for TopDownCameraPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<TopDownView>()
+            .register_type::<TopDownView>()
+            .add_systems(
+                Update,
+                (pan_and_zoom, follow_layer)
+                    .chain()
+                    .after(super::apply_camera_mode)
+                    .run_if(in_world)
+                    .run_if(resource_equals(CameraMode::TopDown)),
+            );
+    }
+}
+
+/// Height of the view in tiles at zoom 1.
+const VIEW_HEIGHT: f32 = 24.0;
+/// Distance of the camera above the current layer.
+const EYE_HEIGHT: f32 = 32.0;
+
+#[derive(Debug, Resource, Reflect)]
+pub struct TopDownView {
+    /// Point on the map in the center of the view, x and z in world space.
+    pub focus: Vec2,
+    pub zoom: f32,
+    /// Tiles per second at zoom 1.
+    pub pan_speed: f32,
+}
+
+impl Default for TopDownView {
+    fn default() -> Self {
+        Self {
+            focus: Vec2::new(8.0, 8.0),
+            zoom: 1.0,
+            pan_speed: 20.0,
+        }
+    }
+}
+
+pub(super) fn projection() -> OrthographicProjection {
+    OrthographicProjection {
+        scaling_mode: ScalingMode::FixedVertical(VIEW_HEIGHT),
+        ..default()
+    }
+}
+
+fn pan_and_zoom(
+    time: Res<Time>,
+    keys: Res<ButtonInput<KeyCode>>,
+    settings: Res<Settings>,
+    mut wheel: EventReader<MouseWheel>,
+    cache: Res<ChunkCache>,
+    mut view: ResMut<TopDownView>,
+) {
+    let mut dir = Vec2::ZERO;
+    let bindings = &settings.keys;
+    if keys.any_pressed([bindings.key(Action::PanUp), KeyCode::ArrowUp]) {
+        dir.y -= 1.0;
+    }
+    if keys.any_pressed([bindings.key(Action::PanDown), KeyCode::ArrowDown]) {
+        dir.y += 1.0;
+    }
+    if keys.any_pressed([bindings.key(Action::PanLeft), KeyCode::ArrowLeft]) {
+        dir.x -= 1.0;
+    }
+    if keys.any_pressed([bindings.key(Action::PanRight), KeyCode::ArrowRight]) {
+        dir.x += 1.0;
+    }
+    if dir != Vec2::ZERO {
+        let speed = view.pan_speed * view.zoom;
+        let focus = view.focus + dir.normalize() * speed * time.delta_seconds();
+        view.focus = clamp_to_map(focus, &cache);
+    }
+
+    for event in wheel.read() {
+        let lines = match event.unit {
+            MouseScrollUnit::Line => event.y,
+            MouseScrollUnit::Pixel => event.y / 32.0,
+        };
+        view.zoom = (view.zoom * (1.0 - lines * 0.1)).clamp(0.1, 10.0);
+    }
+}
+
+fn follow_layer(
+    view: Res<TopDownView>,
+    layer: Res<CurrentMapLayer>,
+    mut cameras: Query<(&mut Projection, &mut LookTransform), With<MainCamera>>,
+) {
+    let height = tile_to_world(UVec3::new(0, layer.0 as u32, 0)).y;
+
+    for (mut projection, mut look) in cameras.iter_mut() {
+        if let Projection::Orthographic(ortho) = projection.as_mut() {
+            if ortho.scale != view.zoom {
+                ortho.scale = view.zoom;
+            }
+        }
+
+        let target = Vec3::new(view.focus.x, height, view.focus.y);
+        let eye = target + Vec3::Y * EYE_HEIGHT;
+        if look.eye != eye || look.target != target {
+            // looking straight down, so "up" on screen is -z
+            *look = LookTransform::new(eye, target, Vec3::NEG_Z);
+        }
+    }
+}
@@ -1,22 +1,63 @@
-use bevy::{ecs::system::SystemParam, prelude::*, utils::HashMap};
+use bevy::{
+    ecs::system::SystemParam, pbr::NotShadowCaster, prelude::*, render::primitives::Aabb,
+    utils::HashMap,
+};
 use rand::{distributions::Standard, prelude::*};
 
+pub mod checksum;
 pub mod data;
+pub mod diagnostics;
+mod gltf;
+pub mod lod;
 pub mod meshing;
+pub mod packed_mesh;
 mod temp_mesh;
+pub use lod::ChunkLod;
 pub use meshing::*;
 
-use super::dwarf_map_flags;
+use super::{
+    dwarf_map_flags,
+    error::MapError,
+    history::{MapHistory, TileEdit},
+    tile_atlas::{TileAtlas, TileRenderMode},
+};
 use crate::prelude::*;
 
 pub const CHUNK_SIZE: usize = 16;
 
-#[derive(Debug, Default, Clone, Copy, Reflect)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Reflect)]
 pub struct Tile {
     visibility: TileVisibility,
     index: usize,
 }
 
+impl Tile {
+    pub const EMPTY: Tile = Tile {
+        visibility: TileVisibility::Empty,
+        index: 0,
+    };
+
+    pub fn solid(index: usize) -> Self {
+        Self {
+            visibility: TileVisibility::Solid,
+            index,
+        }
+    }
+
+    pub fn is_solid(&self) -> bool {
+        matches!(self.visibility, TileVisibility::Solid)
+    }
+
+    pub fn visibility(&self) -> TileVisibility {
+        self.visibility
+    }
+
+    /// The material of the tile, it doubles as index into the [`TileAtlas`].
+    pub fn index(&self) -> usize {
+        self.index
+    }
+}
+
 impl Distribution<Tile> for Standard {
     fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> Tile {
         let visibility = match rng.gen_range(0..2) {
@@ -35,85 +76,235 @@ pub struct ChunkRenderPlugin;
 
 impl Plugin for ChunkRenderPlugin {
     fn build(&self, app: &mut App) {
-        app.init_resource::<ChunkCache>().add_systems(
-            Update,
-            update_chunk_meshes.run_if(in_state(GameState::Playing)),
-        );
+        app.init_resource::<ChunkCache>()
+            .add_plugins(diagnostics::ChunkDiagnosticsPlugin)
+            .init_resource::<TileRenderMode>()
+            .register_type::<TileRenderMode>()
+            .init_resource::<lod::LodSettings>()
+            .register_type::<lod::LodSettings>()
+            .register_type::<ChunkLod>()
+            .register_type::<checksum::ChunkChecksum>()
+            .add_systems(PostUpdate, checksum::update_chunk_checksums)
+            .add_systems(
+                Update,
+                (
+                    (
+                        mark_changed_chunks,
+                        remesh_on_mode_change,
+                        remesh_on_atlas_change,
+                    ),
+                    lod::select_chunk_lod,
+                    update_chunk_meshes,
+                )
+                    .chain()
+                    .run_if(in_world),
+            );
     }
 }
 
+/// Marks a chunk whose meshes are out of date, [`update_chunk_meshes`] rebuilds them and removes it.
+/// Besides the chunk's own tiles the meshes depend on its neighbours, its [`ChunkLod`],
+/// the [`TileRenderMode`] and the [`TileAtlas`], none of which change the [`ChunkData`].
+#[derive(Debug, Component, Default)]
+pub struct NeedsRemesh;
+
+fn mark_changed_chunks(mut commands: Commands, chunks: Query<Entity, Changed<ChunkData>>) {
+    for entity in chunks.iter() {
+        commands.entity(entity).insert(NeedsRemesh);
+    }
+}
+
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
 pub fn update_chunk_meshes(
     mut commands: Commands,
-    chunks: Query<(Entity, &ChunkData, &ChunkCord, Option<&ChunkLayers>), Changed<ChunkData>>,
+    changed: Query<
+        (
+            Entity,
+            &ChunkData,
+            &ChunkCord,
+            &ChunkLod,
+            Option<&ChunkLayers>,
+            Option<&lod::ChunkLodMesh>,
+        ),
+        With<NeedsRemesh>,
+    >,
+    chunks: Query<(&ChunkData, &ChunkLod)>,
     mut mesh_assets: ResMut<Assets<Mesh>>,
-    atlas: Res<super::tile_atlas::TileAtlas>,
+    atlas: Res<TileAtlas>,
+    mode: Res<TileRenderMode>,
+    current_layer: Res<super::CurrentMapLayer>,
     cache: Res<ChunkCache>,
 ) {
-    let material = atlas.material.clone();
     let dummy = ChunkData::default();
 
-    for (c, chunk, cord, old_layers) in chunks.iter() {
-        if let Some(layer) = old_layers {
-            for entity in layer.layers {
-                commands.entity(entity).despawn_recursive();
+    for (c, chunk, cord, lod, old_layers, old_lod_mesh) in changed.iter() {
+        // the new meshes are built before the old ones go, a chunk that fails to mesh keeps its old ones
+        commands.entity(c).remove::<NeedsRemesh>();
+        let despawn_old = |commands: &mut Commands| {
+            if let Some(layer) = old_layers {
+                for entity in layer.layers {
+                    commands.entity(entity).despawn_recursive();
+                }
+                commands.entity(c).remove::<ChunkLayers>();
+            };
+            if let Some(lod_mesh) = old_lod_mesh {
+                commands.entity(lod_mesh.0).despawn_recursive();
+                commands.entity(c).remove::<lod::ChunkLodMesh>();
             }
         };
 
-        let mut meshes: Vec<(Mesh, Mesh)> = vec![];
+        if *lod != ChunkLod::Full {
+            // a chunk entirely above the current layer is cut away completely
+            let Some(max_layer) = current_layer.0.checked_sub(cord.y as usize * CHUNK_SIZE) else {
+                despawn_old(&mut commands);
+                continue;
+            };
+            let cells = lod::downsample(chunk, lod.factor(), max_layer);
+            let mesh = match lod::generate_lod_mesh(&cells, lod.factor(), &atlas, *mode) {
+                Ok(mesh) => mesh,
+                Err(err) => {
+                    error!("Failed to mesh chunk {}: {err}", cord.0);
+                    continue;
+                }
+            };
+            despawn_old(&mut commands);
+
+            if mesh.count_vertices() > 0 {
+                let entity = spawn_layer_mesh(&mut commands, &mut mesh_assets, &atlas, *mode, mesh)
+                    .insert(lod::chunk_aabb())
+                    .insert(Transform::from_xyz(0.0, -super::LAYER_Y_OFFSET, 0.0))
+                    .set_parent(c)
+                    .id();
+                commands.entity(c).insert(lod::ChunkLodMesh(entity));
+            }
+            continue;
+        }
 
-        let neigh = cache.get_neighbors(cord.0);
+        let mut meshes: Vec<(Mesh, Mesh)> = vec![];
 
-        chunk.gen_meshes(
-            neigh[0].map_or(&dummy, |e| chunks.get(e).unwrap().1),
-            neigh[1].map_or(&dummy, |e| chunks.get(e).unwrap().1),
-            neigh[2].map_or(&dummy, |e| chunks.get(e).unwrap().1),
-            neigh[3].map_or(&dummy, |e| chunks.get(e).unwrap().1),
-            neigh[4].map_or(&dummy, |e| chunks.get(e).unwrap().1),
-            neigh[5].map_or(&dummy, |e| chunks.get(e).unwrap().1),
+        // lower detail neighbours draw their own border faces,
+        // so full detail chunks treat them as empty to close the seam from this side as well
+        let neigh = cache.get_neighbors(cord.0).map(|n| {
+            n.and_then(|e| chunks.get(e).ok())
+                .filter(|(_, lod)| **lod == ChunkLod::Full)
+                .map_or(&dummy, |(data, _)| data)
+        });
+
+        let result = chunk.gen_meshes(
+            neigh[0],
+            neigh[1],
+            neigh[2],
+            neigh[3],
+            neigh[4],
+            neigh[5],
             &atlas,
+            *mode,
             &mut meshes,
         );
+        if let Err(err) = result {
+            error!("Failed to mesh chunk {}: {err}", cord.0);
+            continue;
+        }
+        despawn_old(&mut commands);
 
         let mut layers = ChunkLayers {
             layers: [Entity::PLACEHOLDER; CHUNK_SIZE],
         };
 
-        let mut current: usize = cord.y as usize * CHUNK_SIZE;
-
-        for (layer, (floor_wall, ceiling)) in layers.layers.iter_mut().zip(meshes) {
+        for (y, (layer, (floor_wall, ceiling))) in layers.layers.iter_mut().zip(meshes).enumerate()
+        {
+            let current = cord.y as usize * CHUNK_SIZE + y;
             let entity = commands
                 .spawn(ChunkLayer)
                 .insert(SpatialBundle::from_transform(Transform::from_xyz(
                     0.0,
-                    (current - cord.y as usize * CHUNK_SIZE) as f32 - 8.0,
+                    y as f32 - super::LAYER_Y_OFFSET,
                     0.0,
                 )))
                 .set_parent(c)
                 .id();
             *layer = entity;
 
-            commands
-                .spawn(PbrBundle {
-                    mesh: mesh_assets.add(floor_wall),
-                    material: material.clone(),
-                    ..Default::default()
-                })
-                .insert(dwarf_map_flags::WallFloorMesh(current))
-                .set_parent(entity);
-
-            commands
-                .spawn(PbrBundle {
-                    mesh: mesh_assets.add(ceiling),
-                    material: material.clone(),
-                    ..Default::default()
-                })
-                .insert(dwarf_map_flags::CeilingMesh(current))
-                .set_parent(entity);
-
-            current += 1;
+            // layers without any visible face don't need to be submitted at all
+            if floor_wall.count_vertices() > 0 {
+                spawn_layer_mesh(&mut commands, &mut mesh_assets, &atlas, *mode, floor_wall)
+                    .insert(dwarf_map_flags::WallFloorMesh(current))
+                    .set_parent(entity);
+            }
+
+            if ceiling.count_vertices() > 0 {
+                spawn_layer_mesh(&mut commands, &mut mesh_assets, &atlas, *mode, ceiling)
+                    .insert(dwarf_map_flags::CeilingMesh(current))
+                    .set_parent(entity);
+            }
+        }
+
+        commands
+            .entity(c)
+            .insert(layers)
+            .insert(super::culling::LayerOcclusion::new(chunk));
+    }
+}
+
+/// Spawns a mesh with the material matching the [`TileRenderMode`] it was built for.
+fn spawn_layer_mesh<'a>(
+    commands: &'a mut Commands,
+    mesh_assets: &mut Assets<Mesh>,
+    atlas: &TileAtlas,
+    mode: TileRenderMode,
+    mesh: Mesh,
+) -> bevy::ecs::system::EntityCommands<'a> {
+    let stats = diagnostics::ChunkMeshStats::new(&mesh);
+    let mesh = mesh_assets.add(mesh);
+
+    let mut entity = match mode {
+        TileRenderMode::Atlas => commands.spawn(PbrBundle {
+            mesh,
+            material: atlas.material.clone(),
+            ..Default::default()
+        }),
+        TileRenderMode::Array => commands.spawn((
+            MaterialMeshBundle {
+                mesh,
+                material: atlas.array_material.clone(),
+                ..Default::default()
+            },
+            // packed meshes have no position attribute to compute the bounds from
+            Aabb::from_min_max(
+                Vec3::splat(-0.5),
+                Vec3::new(MAX as f32 + 0.5, 0.5, MAX as f32 + 0.5),
+            ),
+            NotShadowCaster,
+        )),
+    };
+    entity.insert(stats);
+    entity
+}
+
+/// The meshes UVs depend on the [`TileRenderMode`], so every chunk has to be rebuild when it changes.
+fn remesh_on_mode_change(
+    mut commands: Commands,
+    mode: Res<TileRenderMode>,
+    chunks: Query<Entity, With<ChunkData>>,
+) {
+    if mode.is_changed() && !mode.is_added() {
+        for entity in chunks.iter() {
+            commands.entity(entity).insert(NeedsRemesh);
         }
+    }
+}
 
-        commands.entity(c).insert(layers);
+/// A reloaded [`TileAtlas`] is only marked as changed when its layout moved,
+/// the uvs of every chunk point into the old one then.
+fn remesh_on_atlas_change(
+    mut commands: Commands,
+    atlas: Res<TileAtlas>,
+    chunks: Query<Entity, With<ChunkData>>,
+) {
+    if atlas.is_changed() && !atlas.is_added() {
+        for entity in chunks.iter() {
+            commands.entity(entity).insert(NeedsRemesh);
+        }
     }
 }
 
@@ -131,6 +322,41 @@ impl ChunkCache {
         self.map.insert(pos, e);
     }
 
+    pub fn iter(&self) -> impl Iterator<Item = (&UVec3, &Entity)> {
+        self.map.iter()
+    }
+
+    /// Reads a tile by its world position.
+    pub fn get_tile(&self, chunks: &Query<&ChunkData>, pos: UVec3) -> Result<Tile, MapError> {
+        let cord = pos / UVec3::splat(CHUNK_SIZE as u32);
+        let chunk = self.get(&cord).ok_or(MapError::OutOfBounds(pos))?;
+        let data = chunks
+            .get(chunk)
+            .map_err(|_| MapError::UnloadedChunk(cord))?;
+        Ok(*data.get_tile_local(pos % UVec3::splat(CHUNK_SIZE as u32)))
+    }
+
+    /// Reads the tiles at `positions`, with an error for tiles outside of loaded chunks.
+    /// Consecutive positions in the same chunk share one chunk lookup,
+    /// the shapes in [`region`](super::region) are ordered chunk by chunk for this.
+    pub fn tiles<'a, I: IntoIterator<Item = UVec3>>(
+        &'a self,
+        chunks: &'a Query<&ChunkData>,
+        positions: I,
+    ) -> Tiles<'a, I::IntoIter> {
+        Tiles::new(self, Box::new(move |e| chunks.get(e).ok()), positions)
+    }
+
+    /// Smallest and largest tile position covered by a loaded chunk.
+    pub fn tile_bounds(&self) -> Option<(UVec3, UVec3)> {
+        let min = self.map.keys().copied().reduce(UVec3::min)?;
+        let max = self.map.keys().copied().reduce(UVec3::max)?;
+        Some((
+            min * CHUNK_SIZE as u32,
+            (max + UVec3::ONE) * CHUNK_SIZE as u32 - UVec3::ONE,
+        ))
+    }
+
     fn get_neighbors(&self, pos: UVec3) -> [Option<Entity>; 6] {
         [
             self.get(&pos.wrapping_add(UVec3::X)),
@@ -143,6 +369,57 @@ impl ChunkCache {
     }
 }
 
+/// Iterator returned by [`ChunkCache::tiles`] and [`MapCommands::tiles`].
+pub struct Tiles<'a, I> {
+    cache: &'a ChunkCache,
+    chunk_data: Box<dyn Fn(Entity) -> Option<&'a ChunkData> + 'a>,
+    positions: I,
+    /// The last chunk and the result of its lookup, reused while the positions stay in it.
+    last: Option<(UVec3, Option<Option<&'a ChunkData>>)>,
+}
+
+impl<'a, I: Iterator<Item = UVec3>> Tiles<'a, I> {
+    fn new(
+        cache: &'a ChunkCache,
+        chunk_data: Box<dyn Fn(Entity) -> Option<&'a ChunkData> + 'a>,
+        positions: impl IntoIterator<IntoIter = I>,
+    ) -> Self {
+        Self {
+            cache,
+            chunk_data,
+            positions: positions.into_iter(),
+            last: None,
+        }
+    }
+}
+
+impl<'a, I: Iterator<Item = UVec3>> Iterator for Tiles<'a, I> {
+    type Item = (UVec3, Result<Tile, MapError>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let pos = self.positions.next()?;
+        let cord = pos / UVec3::splat(CHUNK_SIZE as u32);
+        let chunk = match self.last {
+            Some((last, chunk)) if last == cord => chunk,
+            _ => {
+                let chunk = self.cache.get(&cord).map(&self.chunk_data);
+                self.last = Some((cord, chunk));
+                chunk
+            }
+        };
+        let tile = match chunk {
+            Some(Some(data)) => Ok(*data.get_tile_local(pos % UVec3::splat(CHUNK_SIZE as u32))),
+            Some(None) => Err(MapError::UnloadedChunk(cord)),
+            None => Err(MapError::OutOfBounds(pos)),
+        };
+        Some((pos, tile))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.positions.size_hint()
+    }
+}
+
 #[derive(Component, Default, Debug, Clone)]
 pub struct ChunkData {
     tiles: [[[Tile; CHUNK_SIZE]; CHUNK_SIZE]; CHUNK_SIZE],
@@ -160,79 +437,73 @@ impl ChunkData {
         chunk_back: &ChunkData,
         chunk_top: &ChunkData,
         chunk_bottom: &ChunkData,
-        atlas: &Res<super::tile_atlas::TileAtlas>,
+        atlas: &TileAtlas,
+        mode: TileRenderMode,
         meshes: &mut Vec<(Mesh, Mesh)>,
-    ) {
-        let get_vis = |pos: UVec2, layer_index: usize| -> [TileVisibility; 6] {
-            let x = pos.x as usize;
-            let y = pos.y as usize;
-
-            let layer = self.tiles[layer_index];
-
-            let (left, right) = match x {
-                0 => (
-                    chunk_left.tiles[layer_index][x][MAX].visibility,
-                    layer[x + 1][y].visibility,
-                ),
-                MAX => (
-                    layer[x - 1][y].visibility,
-                    chunk_right.tiles[layer_index][x][0].visibility,
-                ),
-                _ => (layer[x - 1][y].visibility, layer[x + 1][y].visibility),
-            };
-
-            let (front, back) = match y {
-                0 => (
-                    chunk_front.tiles[layer_index][MAX][y].visibility,
-                    layer[x][y + 1].visibility,
-                ),
-                MAX => (
-                    layer[x][y - 1].visibility,
-                    chunk_back.tiles[layer_index][0][y].visibility,
-                ),
-                _ => (layer[x][y - 1].visibility, layer[x][y + 1].visibility),
-            };
-
-            let (above, below) = match layer_index {
-                0 => (self.tiles[layer_index + 1], chunk_bottom.tiles[MAX]),
-                MAX => (chunk_top.tiles[0], self.tiles[layer_index - 1]),
-                _ => (self.tiles[layer_index + 1], self.tiles[layer_index - 1]),
+    ) -> Result<(), MapError> {
+        // tiles relative to this chunk, reaching one tile into the face neighbours,
+        // the chunks along the edges and corners aren't passed in and count as empty
+        let get_vis = |pos: IVec3| -> TileVisibility {
+            let size = CHUNK_SIZE as i32;
+            let outside = pos.cmplt(IVec3::ZERO) | pos.cmpge(IVec3::splat(size));
+            let chunk = match (outside.bitmask(), pos.x, pos.y, pos.z) {
+                (0b000, ..) => self,
+                (0b001, -1, ..) => chunk_left,
+                (0b001, x, ..) if x == size => chunk_right,
+                (0b010, _, -1, _) => chunk_bottom,
+                (0b010, _, y, _) if y == size => chunk_top,
+                (0b100, .., -1) => chunk_back,
+                (0b100, .., z) if z == size => chunk_front,
+                _ => return TileVisibility::Empty,
             };
-
-            [
-                above[x][y].visibility,
-                below[x][y].visibility,
-                right,
-                left,
-                front,
-                back,
-            ]
+            let local = pos.rem_euclid(IVec3::splat(size)).as_uvec3();
+            chunk.get_tile_local(local).visibility
         };
 
         for (i, layer) in self.tiles.iter().enumerate() {
-            meshes.push(meshing::generate_mesh(layer, get_vis, i, atlas));
+            meshes.push(match mode {
+                TileRenderMode::Atlas => {
+                    meshing::generate_mesh::<temp_mesh::TempMesh>(layer, get_vis, i, atlas, mode)
+                }
+                TileRenderMode::Array => meshing::generate_mesh::<packed_mesh::PackedMesh>(
+                    layer, get_vis, i, atlas, mode,
+                ),
+            }?);
         }
+        Ok(())
+    }
+}
+
+impl ChunkData {
+    /// Whether every tile of a layer is solid, such a layer hides everything below it.
+    pub fn solid_layers(&self) -> [bool; CHUNK_SIZE] {
+        self.tiles.map(|layer| {
+            layer
+                .iter()
+                .flatten()
+                .all(|tile| matches!(tile.visibility, TileVisibility::Solid))
+        })
     }
 }
 
 #[allow(unused)]
 impl ChunkData {
-    pub fn random() -> Self {
-        Self {
-            tiles: rand::random(),
-        }
+    pub fn random(rng: &mut impl Rng) -> Self {
+        Self { tiles: rng.gen() }
     }
 
+    // tiles are stored layer first, the same way the meshing reads them
+
     pub fn get_tile_local(&self, pos: UVec3) -> &Tile {
-        &self.tiles[pos.x as usize][pos.y as usize][pos.z as usize]
+        &self.tiles[pos.y as usize][pos.x as usize][pos.z as usize]
     }
 
     pub fn get_tile_local_mut(&mut self, pos: UVec3) -> &mut Tile {
-        &mut self.tiles[pos.x as usize][pos.y as usize][pos.z as usize]
+        &mut self.tiles[pos.y as usize][pos.x as usize][pos.z as usize]
     }
 
     pub fn set_tile_local(&mut self, pos: UVec3, value: Tile) {
-        self.tiles[pos.x as usize][pos.y as usize][pos.z as usize] = value;
+        self.tiles[pos.y as usize][pos.x as usize][pos.z as usize] = value;
     }
 }
 
@@ -249,7 +520,9 @@ pub struct ChunkCord(pub UVec3);
 #[derive(Bundle, Default)]
 pub struct ChunkBundle {
     pub chunk: ChunkData,
+    pub checksum: checksum::ChunkChecksum,
     pub cord: ChunkCord,
+    pub lod: ChunkLod,
     pub visibility: Visibility,
     pub inherited_visibility: InheritedVisibility,
     pub view_visibility: ViewVisibility,
@@ -276,28 +549,87 @@ impl ChunkBundle {
 pub struct MapCommands<'w, 's> {
     commands: Commands<'w, 's>,
     cache: Res<'w, ChunkCache>,
-    chunks: Query<'w, 's, &'static ChunkData>,
+    chunks: Query<'w, 's, &'static mut ChunkData>,
+    history: ResMut<'w, MapHistory>,
 }
 
 #[allow(unused)]
 impl<'w, 's> MapCommands<'w, 's> {
-    pub fn commands(&mut self) -> Commands {
+    pub fn commands(&mut self) -> Commands<'_, '_> {
         self.commands.reborrow()
     }
 
-    /// gets a tile from the world, return panics if the tile is out of bounds.
-    pub fn get_tile<'a>(&'a mut self, pos: UVec3) -> TileCommands<'w, 's, 'a> {
+    /// Whether the tile lies in a loaded chunk.
+    pub fn contains(&self, pos: UVec3) -> bool {
+        self.cache
+            .get(&(pos / UVec3::splat(CHUNK_SIZE as u32)))
+            .is_some()
+    }
+
+    /// Gets a tile from the world, fails if its chunk isn't loaded.
+    pub fn get_tile<'a>(&'a mut self, pos: UVec3) -> Result<TileCommands<'w, 's, 'a>, MapError> {
         let chunk_pos = pos / UVec3::splat(CHUNK_SIZE as u32);
         let chunk = self
             .cache
             .get(&chunk_pos)
-            .expect("Out of bound tile access");
-        TileCommands {
+            .ok_or(MapError::OutOfBounds(pos))?;
+        if !self.chunks.contains(chunk) {
+            return Err(MapError::UnloadedChunk(chunk_pos));
+        }
+        Ok(TileCommands {
             tile: pos,
             local_tile: pos % UVec3::splat(CHUNK_SIZE as u32),
             chunk,
             map_commands: self,
+        })
+    }
+
+    /// Reads the tiles at `positions`, see [`ChunkCache::tiles`].
+    pub fn tiles<'a, I: IntoIterator<Item = UVec3>>(
+        &'a self,
+        positions: I,
+    ) -> Tiles<'a, I::IntoIter> {
+        Tiles::new(
+            &self.cache,
+            Box::new(move |e| self.chunks.get(e).ok()),
+            positions,
+        )
+    }
+
+    /// Groups all following writes into one undo step, until [`commit_transaction`](Self::commit_transaction)
+    /// or the next [`begin_transaction`](Self::begin_transaction).
+    pub fn begin_transaction(&mut self, name: impl Into<String>) {
+        self.history.begin(name);
+    }
+
+    pub fn commit_transaction(&mut self) {
+        self.history.commit();
+    }
+
+    /// Reverts the last transaction, returns what was undone.
+    pub fn undo(&mut self) -> Option<String> {
+        let transaction = self.history.pop_undo()?;
+        for edit in transaction.edits.iter().rev() {
+            if let Ok(mut tile) = self.get_tile(edit.pos) {
+                tile.write(edit.old);
+            }
+        }
+        let message = format!("Undo {}", transaction.name);
+        self.history.push_undone(transaction);
+        Some(message)
+    }
+
+    /// Applies the last undone transaction again, returns what was redone.
+    pub fn redo(&mut self) -> Option<String> {
+        let transaction = self.history.pop_redo()?;
+        for edit in transaction.edits.iter() {
+            if let Ok(mut tile) = self.get_tile(edit.pos) {
+                tile.write(edit.new);
+            }
         }
+        let message = format!("Redo {}", transaction.name);
+        self.history.push_redone(transaction);
+        Some(message)
     }
 }
 
@@ -309,4 +641,183 @@ pub struct TileCommands<'w, 's, 'a> {
     map_commands: &'a mut MapCommands<'w, 's>,
 }
 
-impl<'w, 's, 'a> TileCommands<'w, 's, 'a> {}
+#[allow(unused)]
+impl<'w, 's, 'a> TileCommands<'w, 's, 'a> {
+    /// World position of the tile.
+    pub fn pos(&self) -> UVec3 {
+        self.tile
+    }
+
+    pub fn get(&self) -> Tile {
+        // the chunk data was there in `get_tile`, and nothing can remove it in between
+        self.map_commands
+            .chunks
+            .get(self.chunk)
+            .map(|chunk| *chunk.get_tile_local(self.local_tile))
+            .unwrap_or_default()
+    }
+
+    /// Overwrites the tile, the chunk and neighbouring chunks sharing a face with it get remeshed.
+    /// In the editor the write is recorded in the [`MapHistory`], so it can be undone.
+    pub fn set(&mut self, tile: Tile) {
+        let old = self.get();
+        self.map_commands.history.record(TileEdit {
+            pos: self.tile,
+            old,
+            new: tile,
+        });
+        self.write(tile);
+    }
+
+    fn write(&mut self, tile: Tile) {
+        if let Ok(mut chunk) = self.map_commands.chunks.get_mut(self.chunk) {
+            chunk.set_tile_local(self.local_tile, tile);
+        }
+        self.map_commands.history.wrote(self.tile, tile);
+
+        let chunk_pos = self.tile / UVec3::splat(CHUNK_SIZE as u32);
+        for axis in [UVec3::X, UVec3::Y, UVec3::Z] {
+            let local = self.local_tile.dot(axis);
+            let neighbour = match local {
+                0 => (chunk_pos.dot(axis) > 0).then(|| chunk_pos - axis),
+                MAX_U32 => Some(chunk_pos + axis),
+                _ => None,
+            };
+            // the neighbour's tiles stay the same, only its border faces change
+            if let Some(entity) = neighbour.and_then(|pos| self.map_commands.cache.get(&pos)) {
+                self.map_commands
+                    .commands
+                    .entity(entity)
+                    .try_insert(NeedsRemesh);
+            }
+        }
+    }
+
+    /// Turns a solid tile into an empty one.
+    /// If `drop_item` is set, a boulder of the tile's material is left behind.
+    pub fn dig(&mut self, drop_item: bool) -> Option<Entity> {
+        let tile = self.get();
+        if !tile.is_solid() {
+            return None;
+        }
+
+        self.set(Tile::EMPTY);
+
+        drop_item.then(|| {
+            self.map_commands
+                .commands
+                .spawn(crate::items::ItemBundle::new(
+                    crate::items::ItemKind::Boulder,
+                    tile.index(),
+                    self.tile,
+                ))
+                .id()
+        })
+    }
+}
+
+const MAX_U32: u32 = MAX as u32;
+
+/// A world with the resources [`MapCommands`] needs and an empty chunk at each of `cords`.
+#[cfg(test)]
+pub(crate) fn test_world(cords: impl IntoIterator<Item = UVec3>) -> World {
+    let mut world = World::new();
+    world.init_resource::<ChunkCache>();
+    world.init_resource::<MapHistory>();
+    for cord in cords {
+        let entity = world
+            .spawn(ChunkBundle {
+                cord: ChunkCord(cord),
+                ..default()
+            })
+            .id();
+        world.resource_mut::<ChunkCache>().insert(cord, entity);
+    }
+    world
+}
+
+/// Reads a tile of a [`test_world`], panics outside of its chunks.
+#[cfg(test)]
+pub(crate) fn test_tile(world: &mut World, pos: UVec3) -> Tile {
+    use bevy::ecs::system::RunSystemOnce;
+
+    world.run_system_once(move |mut map: MapCommands| {
+        let tile = map.get_tile(pos).unwrap().get();
+        tile
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::render::mesh::MeshVertexAttribute;
+
+    use super::*;
+
+    fn mesh(chunk: &ChunkData, left: &ChunkData, back: &ChunkData) -> Vec<(Mesh, Mesh)> {
+        let empty = ChunkData::default();
+        let atlas = TileAtlas::for_tests(2);
+        let mut meshes = vec![];
+        chunk
+            .gen_meshes(
+                &empty,
+                left,
+                &empty,
+                back,
+                &empty,
+                &empty,
+                &atlas,
+                TileRenderMode::Atlas,
+                &mut meshes,
+            )
+            .unwrap();
+        meshes
+    }
+
+    fn attribute(mesh: &Mesh, id: MeshVertexAttribute) -> Vec<Vec3> {
+        let values = mesh.attribute(id).unwrap().as_float3().unwrap();
+        values.iter().map(|v| Vec3::from(*v)).collect()
+    }
+
+    #[test]
+    fn mesher_reads_tiles_where_set_tile_local_writes_them() {
+        let mut chunk = ChunkData::default();
+        chunk.set_tile_local(UVec3::new(3, 5, 7), Tile::solid(1));
+
+        let meshes = mesh(&chunk, &ChunkData::default(), &ChunkData::default());
+        for (y, (floor_wall, ceiling)) in meshes.iter().enumerate() {
+            assert_eq!(ceiling.count_vertices(), 0);
+            match y {
+                5 => assert_eq!(floor_wall.count_vertices(), 6 * 4),
+                _ => assert_eq!(floor_wall.count_vertices(), 0, "layer {y}"),
+            }
+        }
+
+        // layer meshes are placed at their layer, the positions only span x and z
+        for pos in attribute(&meshes[5].0, Mesh::ATTRIBUTE_POSITION) {
+            let offset = (pos - Vec3::new(3.0, 0.0, 7.0)).abs();
+            assert!(offset.cmple(Vec3::splat(0.5)).all(), "vertex {pos}");
+        }
+    }
+
+    #[test]
+    fn border_faces_are_culled_by_the_neighbour_chunks() {
+        let mut chunk = ChunkData::default();
+        chunk.set_tile_local(UVec3::new(0, 5, 0), Tile::solid(1));
+        let mut left = ChunkData::default();
+        left.set_tile_local(UVec3::new(15, 5, 0), Tile::solid(1));
+        let mut back = ChunkData::default();
+        back.set_tile_local(UVec3::new(0, 5, 15), Tile::solid(1));
+
+        let meshes = mesh(&chunk, &left, &back);
+        let normals = attribute(&meshes[5].0, Mesh::ATTRIBUTE_NORMAL);
+        assert_eq!(normals.len(), 4 * 4);
+        assert!(!normals.contains(&Vec3::NEG_X));
+        assert!(!normals.contains(&Vec3::NEG_Z));
+
+        // the same tiles on the other side of the neighbours don't touch the chunk
+        let mut far_left = ChunkData::default();
+        far_left.set_tile_local(UVec3::new(0, 5, 0), Tile::solid(1));
+        let meshes = mesh(&chunk, &far_left, &ChunkData::default());
+        assert_eq!(meshes[5].0.count_vertices(), 6 * 4);
+    }
+}
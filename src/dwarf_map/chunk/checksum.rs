@@ -0,0 +1,83 @@
+use std::hash::Hasher;
+
+use bevy::prelude::*;
+
+use super::{ChunkCache, ChunkData};
+use crate::simulation::checksum::StableHasher;
+
+/// Stable hash of the tiles of a chunk, kept up to date by [`update_chunk_checksums`].
+/// Two chunks with the same tiles have the same checksum, on every machine and in every run.
+#[derive(Debug, Component, Default, Clone, Copy, PartialEq, Eq, Reflect)]
+pub struct ChunkChecksum(pub u64);
+
+impl ChunkData {
+    /// Hashes the tiles in storage order, see [`ChunkChecksum`].
+    /// Every field is written out, so it doesn't depend on how std hashes enums and arrays.
+    pub fn checksum(&self) -> u64 {
+        let mut hasher = StableHasher::default();
+        for tile in self.tiles.iter().flatten().flatten() {
+            hasher.write_u8(tile.visibility as u8);
+            hasher.write_u64(tile.index as u64);
+        }
+        hasher.finish()
+    }
+}
+
+impl ChunkCache {
+    /// Combined checksum of all loaded chunks, ordered by their coordinates,
+    /// so it depends neither on the order of the cache nor on entity ids.
+    /// Chunks without a [`ChunkChecksum`] are left out.
+    pub fn world_checksum(&self, checksums: &Query<&ChunkChecksum>) -> u64 {
+        let mut chunks: Vec<_> = self
+            .iter()
+            .filter_map(|(cord, entity)| Some((cord.to_array(), checksums.get(*entity).ok()?.0)))
+            .collect();
+        chunks.sort_unstable();
+
+        let mut hasher = StableHasher::default();
+        for (cord, checksum) in chunks {
+            for v in cord {
+                hasher.write_u32(v);
+            }
+            hasher.write_u64(checksum);
+        }
+        hasher.finish()
+    }
+}
+
+pub fn update_chunk_checksums(
+    mut chunks: Query<(&ChunkData, &mut ChunkChecksum), Changed<ChunkData>>,
+) {
+    for (chunk, mut checksum) in chunks.iter_mut() {
+        let new = ChunkChecksum(chunk.checksum());
+        // writes may put back the tile that was there, like an undone edit
+        if *checksum != new {
+            *checksum = new;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dwarf_map::chunk::Tile;
+
+    // pinned, a change here breaks every saved replay
+
+    #[test]
+    fn empty_chunk_checksum_is_pinned() {
+        assert_eq!(ChunkData::default().checksum(), 0xbe71_3431_5f05_6325);
+    }
+
+    #[test]
+    fn chunk_checksum_follows_the_storage_order() {
+        let mut chunk = ChunkData::default();
+        chunk.set_tile_local(UVec3::new(1, 2, 3), Tile::solid(5));
+        assert_eq!(chunk.checksum(), 0x8d66_78c6_5a11_8e17);
+
+        // the same tile on another position
+        let mut moved = ChunkData::default();
+        moved.set_tile_local(UVec3::new(2, 3, 1), Tile::solid(5));
+        assert_ne!(moved.checksum(), chunk.checksum());
+    }
+}
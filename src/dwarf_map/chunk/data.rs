@@ -90,83 +90,94 @@ pub const FRONT_NORMALS: [Vec3; 4] = [
 
 pub mod cube {
 
-    use crate::dwarf_map::chunk::temp_mesh::TempMesh;
+    use crate::dwarf_map::{chunk::temp_mesh::MeshBuilder, MapError};
 
     use super::*;
 
-    const INDICES: [u32; 6] = [0, 3, 1, 1, 3, 2];
-    const REV_INDICES: [u32; 6] = [0, 1, 3, 1, 2, 3];
-
-    fn offset_vertices(vertices: &[Vec3; 4], offset: &Vec3) -> [Vec3; 4] {
-        [
-            vertices[0] + *offset,
-            vertices[1] + *offset,
-            vertices[2] + *offset,
-            vertices[3] + *offset,
-        ]
-    }
-
-    pub fn add_ceiling(mesh: &mut TempMesh, offset: &Vec3, uvs: [Vec2; 4]) {
-        mesh.extend(
-            &uvs,
-            &CEILING_NORMALS,
-            &offset_vertices(&CEILING_VERTICES, offset),
-            &INDICES,
-        );
-    }
-
-    #[allow(unused)]
-    pub fn add_floor(mesh: &mut TempMesh, offset: &Vec3, uvs: [Vec2; 4]) {
-        mesh.extend(
-            &uvs,
-            &FLOOR_NORMALS,
-            &offset_vertices(&FLOOR_VERTICES, offset),
-            &INDICES,
-        );
-    }
-
-    pub fn add_bottom(mesh: &mut TempMesh, offset: &Vec3, uvs: [Vec2; 4]) {
-        mesh.extend(
-            &uvs,
-            &FLOOR_NORMALS,
-            &offset_vertices(&FLOOR_VERTICES, offset),
-            &REV_INDICES,
-        );
-    }
-
-    pub fn add_front(mesh: &mut TempMesh, offset: &Vec3, uvs: [Vec2; 4]) {
-        mesh.extend(
-            &uvs,
-            &FRONT_NORMALS,
-            &offset_vertices(&FRONT_VERTICES, offset),
-            &REV_INDICES,
-        );
-    }
-
-    pub fn add_back(mesh: &mut TempMesh, offset: &Vec3, uvs: [Vec2; 4]) {
-        mesh.extend(
-            &uvs,
-            &BACK_NORMALS,
-            &offset_vertices(&BACK_VERTICES, offset),
-            &INDICES,
-        );
+    pub const INDICES: [u32; 6] = [0, 3, 1, 1, 3, 2];
+    pub const REV_INDICES: [u32; 6] = [0, 1, 3, 1, 2, 3];
+
+    /// The six faces of a tile, in the order of the neighbours given to
+    /// [`generate_mesh`](super::super::meshing::generate_mesh)
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Face {
+        Top,
+        Bottom,
+        Right,
+        Left,
+        Front,
+        Back,
     }
 
-    pub fn add_right(mesh: &mut TempMesh, offset: &Vec3, uvs: [Vec2; 4]) {
-        mesh.extend(
-            &uvs,
-            &RIGHT_NORMALS,
-            &offset_vertices(&RIGHT_VERTICES, offset),
-            &INDICES,
-        );
+    impl Face {
+        pub const ALL: [Face; 6] = [
+            Face::Top,
+            Face::Bottom,
+            Face::Right,
+            Face::Left,
+            Face::Front,
+            Face::Back,
+        ];
+
+        /// Direction of the neighbour the face looks at
+        pub fn normal(self) -> IVec3 {
+            self.normals()[0].as_ivec3()
+        }
+
+        pub fn vertices(self) -> &'static [Vec3; 4] {
+            match self {
+                Face::Top => &CEILING_VERTICES,
+                Face::Bottom => &FLOOR_VERTICES,
+                Face::Right => &RIGHT_VERTICES,
+                Face::Left => &LEFT_VERTICES,
+                Face::Front => &FRONT_VERTICES,
+                Face::Back => &BACK_VERTICES,
+            }
+        }
+
+        pub fn normals(self) -> &'static [Vec3; 4] {
+            match self {
+                Face::Top => &CEILING_NORMALS,
+                Face::Bottom => &FLOOR_NORMALS,
+                Face::Right => &RIGHT_NORMALS,
+                Face::Left => &LEFT_NORMALS,
+                Face::Front => &FRONT_NORMALS,
+                Face::Back => &BACK_NORMALS,
+            }
+        }
+
+        pub fn indices(self) -> &'static [u32; 6] {
+            match self {
+                Face::Top | Face::Right | Face::Back => &INDICES,
+                Face::Bottom | Face::Left | Face::Front => &REV_INDICES,
+            }
+        }
+
+        /// The extent of the texture on a face covering `size` tiles along x and z
+        pub fn uv_size(self, size: Vec3) -> Vec2 {
+            match self {
+                Face::Top | Face::Bottom => Vec2::new(size.x, size.z),
+                Face::Right | Face::Left => Vec2::new(size.z, size.y),
+                Face::Front | Face::Back => Vec2::new(size.x, size.y),
+            }
+        }
     }
 
-    pub fn add_left(mesh: &mut TempMesh, offset: &Vec3, uvs: [Vec2; 4]) {
-        mesh.extend(
-            &uvs,
-            &LEFT_NORMALS,
-            &offset_vertices(&LEFT_VERTICES, offset),
-            &REV_INDICES,
-        );
+    /// Add a face covering `size` tiles, starting at the tile at `offset`
+    pub fn add_face(
+        mesh: &mut impl MeshBuilder,
+        face: Face,
+        offset: Vec3,
+        size: Vec3,
+        uvs: [Vec2; 4],
+        ao: [u32; 4],
+        layer: u32,
+    ) -> Result<(), MapError> {
+        // the face vertices are the corners of a tile centered on 0, stretch them
+        // away from the first tile
+        let vertices = face
+            .vertices()
+            .map(|v| v + offset + (v + 0.5) * (size - Vec3::ONE));
+        mesh.extend(&uvs, face.normals(), &vertices, &ao, face.indices(), layer)
     }
 }
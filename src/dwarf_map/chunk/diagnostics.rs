@@ -0,0 +1,74 @@
+use bevy::{
+    diagnostic::{Diagnostic, DiagnosticPath, Diagnostics, RegisterDiagnostic},
+    prelude::*,
+    render::{mesh::Indices, render_resource::VertexFormat},
+};
+
+/// Registers diagnostics about the size of the chunk meshes,
+/// they show up in the `LogDiagnosticsPlugin` output.
+pub struct ChunkDiagnosticsPlugin;
+
+impl Plugin for ChunkDiagnosticsPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_diagnostic(Diagnostic::new(CHUNK_VERTICES).with_smoothing_factor(0.0))
+            .register_diagnostic(
+                Diagnostic::new(CHUNK_VERTEX_BYTES)
+                    .with_suffix("KiB")
+                    .with_smoothing_factor(0.0),
+            )
+            .register_diagnostic(
+                Diagnostic::new(CHUNK_INDEX_BYTES)
+                    .with_suffix("KiB")
+                    .with_smoothing_factor(0.0),
+            )
+            .add_systems(Update, chunk_mesh_diagnostics);
+    }
+}
+
+pub const CHUNK_VERTICES: DiagnosticPath = DiagnosticPath::const_new("chunk/vertices");
+pub const CHUNK_VERTEX_BYTES: DiagnosticPath = DiagnosticPath::const_new("chunk/vertex_bytes");
+pub const CHUNK_INDEX_BYTES: DiagnosticPath = DiagnosticPath::const_new("chunk/index_bytes");
+
+/// Size of a layer mesh, recorded on spawn because the mesh data
+/// only lives in the render world afterwards.
+#[derive(Debug, Component, Default, Clone, Copy)]
+pub struct ChunkMeshStats {
+    pub vertices: usize,
+    pub vertex_bytes: usize,
+    pub index_bytes: usize,
+}
+
+impl ChunkMeshStats {
+    pub fn new(mesh: &Mesh) -> Self {
+        let vertex_bytes = mesh
+            .attributes()
+            .map(|(_, values)| VertexFormat::from(values).size() as usize * values.len())
+            .sum();
+
+        let index_bytes = match mesh.indices() {
+            Some(Indices::U16(i)) => i.len() * 2,
+            Some(Indices::U32(i)) => i.len() * 4,
+            None => 0,
+        };
+
+        Self {
+            vertices: mesh.count_vertices(),
+            vertex_bytes,
+            index_bytes,
+        }
+    }
+}
+
+fn chunk_mesh_diagnostics(mut diagnostics: Diagnostics, stats: Query<&ChunkMeshStats>) {
+    let total = stats
+        .iter()
+        .fold(ChunkMeshStats::default(), |acc, s| ChunkMeshStats {
+            vertices: acc.vertices + s.vertices,
+            vertex_bytes: acc.vertex_bytes + s.vertex_bytes,
+            index_bytes: acc.index_bytes + s.index_bytes,
+        });
+
+    diagnostics.add_measurement(&CHUNK_VERTICES, || total.vertices as f64);
+    diagnostics.add_measurement(&CHUNK_VERTEX_BYTES, || total.vertex_bytes as f64 / 1024.0);
+    diagnostics.add_measurement(&CHUNK_INDEX_BYTES, || total.index_bytes as f64 / 1024.0);
+}
@@ -0,0 +1,397 @@
+//! Binary glTF export of chunk meshes, to look at faces and winding in other tools.
+//!
+//! The meshes are generated again in [`TileRenderMode::Atlas`], so the file has the same
+//! positions, normals and UVs the atlas path renders, no matter which mode the game uses.
+//! Materials are single sided, faces with the wrong winding show up as holes.
+
+use std::fmt::Write as _;
+
+use bevy::render::mesh::{Indices, VertexAttributeValues};
+
+use super::*;
+use crate::dwarf_map::LAYER_Y_OFFSET;
+
+const ARRAY_BUFFER: u32 = 34962;
+const ELEMENT_ARRAY_BUFFER: u32 = 34963;
+const FLOAT: u32 = 5126;
+const UNSIGNED_INT: u32 = 5125;
+const NEAREST: u32 = 9728;
+
+impl<'w, 's> MapCommands<'w, 's> {
+    /// Meshes the given chunks at full detail and returns them as a `.glb` file,
+    /// with one node per layer and mesh kind, named like the entities in the game.
+    /// Chunks that aren't loaded are skipped, missing neighbours count as empty.
+    pub fn chunks_to_glb(
+        &self,
+        cords: impl IntoIterator<Item = UVec3>,
+        atlas: &TileAtlas,
+        images: &Assets<Image>,
+    ) -> Result<Vec<u8>, MapError> {
+        let dummy = ChunkData::default();
+        let mut gltf = GltfBuilder::default();
+
+        for cord in cords {
+            let Some(chunk) = self.cache.get(&cord).and_then(|e| self.chunks.get(e).ok()) else {
+                continue;
+            };
+            let neigh = self
+                .cache
+                .get_neighbors(cord)
+                .map(|n| n.and_then(|e| self.chunks.get(e).ok()).unwrap_or(&dummy));
+
+            let mut meshes = vec![];
+            chunk.gen_meshes(
+                neigh[0],
+                neigh[1],
+                neigh[2],
+                neigh[3],
+                neigh[4],
+                neigh[5],
+                atlas,
+                TileRenderMode::Atlas,
+                &mut meshes,
+            )?;
+
+            for (layer, (floor_wall, ceiling)) in meshes.iter().enumerate() {
+                let world_layer = cord.y as usize * CHUNK_SIZE + layer;
+                let translation = (cord * CHUNK_SIZE as u32).as_vec3()
+                    + Vec3::Y * (layer as f32 - LAYER_Y_OFFSET);
+                gltf.add_mesh(
+                    &format!("WallFloorMesh({world_layer}) chunk {cord}"),
+                    translation,
+                    floor_wall,
+                );
+                gltf.add_mesh(
+                    &format!("CeilingMesh({world_layer}) chunk {cord}"),
+                    translation,
+                    ceiling,
+                );
+            }
+        }
+
+        if let Some(image) = images.get(&atlas.image) {
+            gltf.add_image(image);
+        }
+        Ok(gltf.finish())
+    }
+}
+
+#[derive(Default)]
+struct GltfBuilder {
+    bin: Vec<u8>,
+    buffer_views: Vec<String>,
+    accessors: Vec<String>,
+    meshes: Vec<String>,
+    nodes: Vec<String>,
+    image: Option<usize>,
+}
+
+impl GltfBuilder {
+    /// Appends `data` to the binary chunk, returns the index of its buffer view.
+    fn push_view(&mut self, data: &[u8], target: Option<u32>) -> usize {
+        let offset = self.bin.len();
+        self.bin.extend(data);
+        // accessors need their data aligned to the component size
+        self.bin.resize(self.bin.len().next_multiple_of(4), 0);
+
+        let target = target.map_or(String::new(), |t| format!(r#","target":{t}"#));
+        self.buffer_views.push(format!(
+            r#"{{"buffer":0,"byteOffset":{offset},"byteLength":{}{target}}}"#,
+            data.len()
+        ));
+        self.buffer_views.len() - 1
+    }
+
+    fn push_accessor(
+        &mut self,
+        view: usize,
+        component: u32,
+        count: usize,
+        kind: &str,
+        extra: &str,
+    ) -> usize {
+        self.accessors.push(format!(
+            r#"{{"bufferView":{view},"componentType":{component},"count":{count},"type":"{kind}"{extra}}}"#
+        ));
+        self.accessors.len() - 1
+    }
+
+    fn add_mesh(&mut self, name: &str, translation: Vec3, mesh: &Mesh) {
+        let (
+            Some(VertexAttributeValues::Float32x3(positions)),
+            Some(VertexAttributeValues::Float32x3(normals)),
+            Some(VertexAttributeValues::Float32x2(uvs)),
+            Some(Indices::U32(indices)),
+        ) = (
+            mesh.attribute(Mesh::ATTRIBUTE_POSITION),
+            mesh.attribute(Mesh::ATTRIBUTE_NORMAL),
+            mesh.attribute(Mesh::ATTRIBUTE_UV_0),
+            mesh.indices(),
+        )
+        else {
+            warn!("{name} is missing an attribute the export needs, skipping it");
+            return;
+        };
+        if positions.is_empty() {
+            return;
+        }
+
+        let min = positions
+            .iter()
+            .copied()
+            .map(Vec3::from)
+            .reduce(Vec3::min)
+            .unwrap_or_default();
+        let max = positions
+            .iter()
+            .copied()
+            .map(Vec3::from)
+            .reduce(Vec3::max)
+            .unwrap_or_default();
+
+        let view = self.push_view(&float_bytes(positions.iter().flatten()), Some(ARRAY_BUFFER));
+        let bounds = format!(
+            r#","min":[{},{},{}],"max":[{},{},{}]"#,
+            min.x, min.y, min.z, max.x, max.y, max.z
+        );
+        let position = self.push_accessor(view, FLOAT, positions.len(), "VEC3", &bounds);
+
+        let view = self.push_view(&float_bytes(normals.iter().flatten()), Some(ARRAY_BUFFER));
+        let normal = self.push_accessor(view, FLOAT, normals.len(), "VEC3", "");
+
+        let view = self.push_view(&float_bytes(uvs.iter().flatten()), Some(ARRAY_BUFFER));
+        let uv = self.push_accessor(view, FLOAT, uvs.len(), "VEC2", "");
+
+        let view = self.push_view(
+            &indices
+                .iter()
+                .flat_map(|i| i.to_le_bytes())
+                .collect::<Vec<_>>(),
+            Some(ELEMENT_ARRAY_BUFFER),
+        );
+        let index = self.push_accessor(view, UNSIGNED_INT, indices.len(), "SCALAR", "");
+
+        self.meshes.push(format!(
+            r#"{{"name":"{name}","primitives":[{{"attributes":{{"POSITION":{position},"NORMAL":{normal},"TEXCOORD_0":{uv}}},"indices":{index},"material":0}}]}}"#
+        ));
+        self.nodes.push(format!(
+            r#"{{"name":"{name}","mesh":{},"translation":[{},{},{}]}}"#,
+            self.meshes.len() - 1,
+            translation.x,
+            translation.y,
+            translation.z
+        ));
+    }
+
+    /// Embeds the atlas as png, only 8 bit RGBA images are supported.
+    fn add_image(&mut self, image: &Image) {
+        if image.texture_descriptor.format.block_copy_size(None) != Some(4) {
+            warn!(
+                "Atlas format {:?} can't be exported",
+                image.texture_descriptor.format
+            );
+            return;
+        }
+        let png = encode_png(image.width(), image.height(), &image.data);
+        self.image = Some(self.push_view(&png, None));
+    }
+
+    fn finish(self) -> Vec<u8> {
+        let nodes = (0..self.nodes.len())
+            .map(|i| i.to_string())
+            .collect::<Vec<_>>();
+
+        let mut json =
+            String::from(r#"{"asset":{"version":"2.0","generator":"dwarf_map"},"scene":0,"#);
+        let _ = write!(json, r#""scenes":[{{"nodes":[{}]}}],"#, nodes.join(","));
+        let _ = write!(json, r#""nodes":[{}],"#, self.nodes.join(","));
+        let _ = write!(json, r#""meshes":[{}],"#, self.meshes.join(","));
+        let _ = write!(json, r#""accessors":[{}],"#, self.accessors.join(","));
+        let _ = write!(json, r#""bufferViews":[{}],"#, self.buffer_views.join(","));
+        let _ = write!(json, r#""buffers":[{{"byteLength":{}}}],"#, self.bin.len());
+
+        let texture = match self.image {
+            Some(view) => {
+                let _ = write!(
+                    json,
+                    r#""images":[{{"bufferView":{view},"mimeType":"image/png"}}],"samplers":[{{"magFilter":{NEAREST},"minFilter":{NEAREST}}}],"textures":[{{"source":0,"sampler":0}}],"#
+                );
+                r#""baseColorTexture":{"index":0},"#
+            }
+            None => "",
+        };
+        let _ = write!(
+            json,
+            r#""materials":[{{"name":"TileAtlas","pbrMetallicRoughness":{{{texture}"metallicFactor":0}}}}]}}"#
+        );
+
+        let mut json = json.into_bytes();
+        json.resize(json.len().next_multiple_of(4), b' ');
+        let mut bin = self.bin;
+        bin.resize(bin.len().next_multiple_of(4), 0);
+
+        let mut out = vec![];
+        out.extend(b"glTF");
+        out.extend(2u32.to_le_bytes());
+        out.extend((12 + 8 + json.len() as u32 + 8 + bin.len() as u32).to_le_bytes());
+        out.extend((json.len() as u32).to_le_bytes());
+        out.extend(b"JSON");
+        out.extend(json);
+        out.extend((bin.len() as u32).to_le_bytes());
+        out.extend(b"BIN\0");
+        out.extend(bin);
+        out
+    }
+}
+
+fn float_bytes<'a>(values: impl IntoIterator<Item = &'a f32>) -> Vec<u8> {
+    values.into_iter().flat_map(|v| v.to_le_bytes()).collect()
+}
+
+/// Uncompressed png, the atlas is small enough that deflate isn't worth a dependency.
+fn encode_png(width: u32, height: u32, rgba: &[u8]) -> Vec<u8> {
+    let row = width as usize * 4;
+    let mut raw = Vec::with_capacity((row + 1) * height as usize);
+    for line in rgba.chunks_exact(row) {
+        // filter type none
+        raw.push(0);
+        raw.extend(line);
+    }
+
+    // zlib stream made of stored deflate blocks
+    let mut zlib = vec![0x78, 0x01];
+    let mut blocks = raw.chunks(u16::MAX as usize).peekable();
+    if blocks.peek().is_none() {
+        zlib.extend([1, 0, 0, 0xff, 0xff]);
+    }
+    while let Some(block) = blocks.next() {
+        zlib.push(blocks.peek().is_none() as u8);
+        zlib.extend((block.len() as u16).to_le_bytes());
+        zlib.extend((!(block.len() as u16)).to_le_bytes());
+        zlib.extend(block);
+    }
+    zlib.extend(adler32(&raw).to_be_bytes());
+
+    let mut header = vec![];
+    header.extend(width.to_be_bytes());
+    header.extend(height.to_be_bytes());
+    // 8 bit depth, RGBA, default compression, filter and no interlacing
+    header.extend([8, 6, 0, 0, 0]);
+
+    let mut out = vec![0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+    write_png_chunk(&mut out, b"IHDR", &header);
+    write_png_chunk(&mut out, b"IDAT", &zlib);
+    write_png_chunk(&mut out, b"IEND", &[]);
+    out
+}
+
+fn write_png_chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    out.extend((data.len() as u32).to_be_bytes());
+    let start = out.len();
+    out.extend(kind);
+    out.extend(data);
+    let crc = crc32(&out[start..]);
+    out.extend(crc.to_be_bytes());
+}
+
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in bytes {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xedb8_8320 & (crc & 1).wrapping_neg());
+        }
+    }
+    !crc
+}
+
+fn adler32(bytes: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for byte in bytes {
+        a = (a + *byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    b << 16 | a
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::RunSystemOnce;
+
+    use super::*;
+
+    fn u32_at(bytes: &[u8], offset: usize) -> usize {
+        u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap()) as usize
+    }
+
+    /// The `count` of every accessor, in the order they were written.
+    fn accessor_counts(json: &str) -> Vec<usize> {
+        json.split(r#""count":"#)
+            .skip(1)
+            .map(|rest| {
+                let end = rest.find(|c: char| !c.is_ascii_digit()).unwrap();
+                rest[..end].parse().unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn a_single_tile_exports_as_valid_glb() {
+        let mut chunk = ChunkData::default();
+        chunk.set_tile_local(UVec3::new(3, 5, 7), Tile::solid(1));
+        let atlas = TileAtlas::for_tests(2);
+
+        let mut expected = vec![];
+        let empty = ChunkData::default();
+        let mut meshes = vec![];
+        chunk
+            .gen_meshes(
+                &empty,
+                &empty,
+                &empty,
+                &empty,
+                &empty,
+                &empty,
+                &atlas,
+                TileRenderMode::Atlas,
+                &mut meshes,
+            )
+            .unwrap();
+        for mesh in meshes
+            .iter()
+            .flat_map(|(floor_wall, ceiling)| [floor_wall, ceiling])
+        {
+            let vertices = mesh.count_vertices();
+            if vertices > 0 {
+                let indices = mesh.indices().unwrap().len();
+                expected.extend([vertices, vertices, vertices, indices]);
+            }
+        }
+        assert!(!expected.is_empty());
+
+        let mut world = test_world([UVec3::ZERO]);
+        let entity = world.resource::<ChunkCache>().get(&UVec3::ZERO).unwrap();
+        world.entity_mut(entity).insert(chunk);
+        let glb = world.run_system_once(move |map: MapCommands| {
+            map.chunks_to_glb([UVec3::ZERO], &atlas, &Assets::default())
+        });
+        let glb = glb.unwrap();
+
+        assert_eq!(&glb[0..4], b"glTF");
+        assert_eq!(u32_at(&glb, 4), 2);
+        assert_eq!(u32_at(&glb, 8), glb.len());
+
+        let json_len = u32_at(&glb, 12);
+        assert_eq!(json_len % 4, 0);
+        assert_eq!(&glb[16..20], b"JSON");
+        let json = std::str::from_utf8(&glb[20..20 + json_len]).unwrap();
+
+        let bin = 20 + json_len;
+        let bin_len = u32_at(&glb, bin);
+        assert_eq!(bin_len % 4, 0);
+        assert_eq!(&glb[bin + 4..bin + 8], b"BIN\0");
+        assert_eq!(bin + 8 + bin_len, glb.len());
+
+        assert_eq!(accessor_counts(json), expected);
+    }
+}
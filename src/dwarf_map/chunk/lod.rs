@@ -0,0 +1,225 @@
+use bevy::{prelude::*, render::primitives::Aabb, utils::HashMap};
+
+use super::{
+    data::cube::{add_face, Face},
+    meshing::face_ao,
+    packed_mesh::PackedMesh,
+    temp_mesh::{MeshBuilder, TempMesh},
+    ChunkCache, ChunkCord, ChunkData, NeedsRemesh, Tile, TileVisibility, CHUNK_SIZE,
+};
+use crate::{
+    camera::MainCamera,
+    dwarf_map::{
+        tile_atlas::{TileAtlas, TileRenderMode},
+        CurrentMapLayer, MapError, LAYER_Y_OFFSET,
+    },
+};
+
+/// Level of detail a chunk is meshed with.
+#[derive(Debug, Component, Reflect, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ChunkLod {
+    /// One layer mesh per tile layer.
+    #[default]
+    Full,
+    /// One mesh for the whole chunk, 2×2×2 tiles merged into one cell.
+    Half,
+    /// One mesh for the whole chunk, 4×4×4 tiles merged into one cell.
+    Quarter,
+}
+
+impl ChunkLod {
+    /// Tiles per cell along each axis.
+    pub fn factor(&self) -> usize {
+        match self {
+            ChunkLod::Full => 1,
+            ChunkLod::Half => 2,
+            ChunkLod::Quarter => 4,
+        }
+    }
+}
+
+#[derive(Debug, Resource, Reflect)]
+pub struct LodSettings {
+    pub enabled: bool,
+    /// Distance from the camera to the chunk center from which on [`ChunkLod::Half`] is used.
+    pub half_distance: f32,
+    /// Distance from the camera to the chunk center from which on [`ChunkLod::Quarter`] is used.
+    pub quarter_distance: f32,
+}
+
+impl Default for LodSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            half_distance: 64.0,
+            quarter_distance: 128.0,
+        }
+    }
+}
+
+impl LodSettings {
+    pub fn level_for(&self, distance: f32) -> ChunkLod {
+        match distance {
+            _ if !self.enabled => ChunkLod::Full,
+            d if d >= self.quarter_distance => ChunkLod::Quarter,
+            d if d >= self.half_distance => ChunkLod::Half,
+            _ => ChunkLod::Full,
+        }
+    }
+}
+
+/// The single mesh of a chunk that is not meshed at [`ChunkLod::Full`].
+#[derive(Debug, Component)]
+pub struct ChunkLodMesh(pub Entity);
+
+/// Picks the level of detail of every chunk based on the camera distance.
+/// Changing the level remeshes the chunk and its neighbours,
+/// because full detail chunks draw their border faces next to lower detail ones.
+pub fn select_chunk_lod(
+    mut commands: Commands,
+    settings: Res<LodSettings>,
+    layer: Res<CurrentMapLayer>,
+    cache: Res<ChunkCache>,
+    cameras: Query<&GlobalTransform, With<MainCamera>>,
+    mut chunks: Query<(Entity, &ChunkCord, &GlobalTransform, &mut ChunkLod)>,
+) {
+    let Ok(camera) = cameras.get_single() else {
+        return;
+    };
+    let eye = camera.translation();
+
+    for (entity, cord, transform, mut lod) in chunks.iter_mut() {
+        let center = transform.translation() + Vec3::splat(CHUNK_SIZE as f32 / 2.0 - 0.5)
+            - Vec3::Y * LAYER_Y_OFFSET;
+        let level = settings.level_for(center.distance(eye));
+
+        if *lod != level {
+            *lod = level;
+            for neighbour in cache.get_neighbors(cord.0).into_iter().flatten() {
+                commands.entity(neighbour).try_insert(NeedsRemesh);
+            }
+            commands.entity(entity).insert(NeedsRemesh);
+        } else if layer.is_changed() && level != ChunkLod::Full {
+            // the cut at the current layer is baked into the mesh
+            commands.entity(entity).insert(NeedsRemesh);
+        }
+    }
+}
+
+/// Downsamples a chunk, every cell takes the majority of the tiles it covers.
+/// Tiles above `max_layer` are treated as empty, so the cut view still works.
+pub fn downsample(chunk: &ChunkData, factor: usize, max_layer: usize) -> Vec<Tile> {
+    let cells = CHUNK_SIZE / factor;
+    let mut out = Vec::with_capacity(cells * cells * cells);
+
+    for cy in 0..cells {
+        for cx in 0..cells {
+            for cz in 0..cells {
+                let mut solid = 0;
+                let mut counts = HashMap::<usize, usize>::new();
+                for y in cy * factor..(cy + 1) * factor {
+                    if y > max_layer {
+                        continue;
+                    }
+                    for x in cx * factor..(cx + 1) * factor {
+                        for z in cz * factor..(cz + 1) * factor {
+                            let tile = chunk.tiles[y][x][z];
+                            if let TileVisibility::Solid = tile.visibility {
+                                solid += 1;
+                                *counts.entry(tile.index).or_default() += 1;
+                            }
+                        }
+                    }
+                }
+
+                let visibility = match solid * 2 >= factor * factor * factor {
+                    true => TileVisibility::Solid,
+                    false => TileVisibility::Empty,
+                };
+                // ties are broken by the lower index, so the result doesn't depend on iteration order
+                let index = counts
+                    .into_iter()
+                    .max_by_key(|(index, count)| (*count, std::cmp::Reverse(*index)))
+                    .map_or(0, |(index, _)| index);
+                out.push(Tile { visibility, index });
+            }
+        }
+    }
+
+    out
+}
+
+/// Meshes a downsampled chunk with the same face culling rules as the full detail meshing.
+/// Faces on the chunk border are always generated, they act as skirts that hide
+/// the gaps between chunks with a different level of detail.
+pub fn generate_lod_mesh(
+    cells: &[Tile],
+    factor: usize,
+    atlas: &TileAtlas,
+    mode: TileRenderMode,
+) -> Result<Mesh, MapError> {
+    match mode {
+        TileRenderMode::Atlas => build_lod_mesh::<TempMesh>(cells, factor, atlas, mode),
+        TileRenderMode::Array => build_lod_mesh::<PackedMesh>(cells, factor, atlas, mode),
+    }
+}
+
+fn build_lod_mesh<M: MeshBuilder>(
+    cells: &[Tile],
+    factor: usize,
+    atlas: &TileAtlas,
+    mode: TileRenderMode,
+) -> Result<Mesh, MapError> {
+    let size = (CHUNK_SIZE / factor) as i32;
+    let get = |x: i32, y: i32, z: i32| -> TileVisibility {
+        match [x, y, z].iter().all(|v| (0..size).contains(v)) {
+            true => cells[((y * size + x) * size + z) as usize].visibility,
+            false => TileVisibility::Empty,
+        }
+    };
+    let solid = |pos: IVec3| get(pos.x, pos.y, pos.z) == TileVisibility::Solid;
+
+    let scale = factor as f32;
+    let mut mesh = M::default();
+
+    for y in 0..size {
+        for x in 0..size {
+            for z in 0..size {
+                let tile = cells[((y * size + x) * size + z) as usize];
+                let vis = tile.visibility;
+                if let TileVisibility::Empty = vis {
+                    continue;
+                }
+
+                // first tile of the cell, the face is stretched over the whole cell
+                let offset = Vec3::new(x as f32, y as f32, z as f32) * scale;
+                let uvs = atlas.get_face_uvs(tile.index, mode, Vec2::splat(scale))?;
+                let layer = tile.index as u32;
+
+                let neighbours = [
+                    get(x, y + 1, z),
+                    get(x, y - 1, z),
+                    get(x + 1, y, z),
+                    get(x - 1, y, z),
+                    get(x, y, z - 1),
+                    get(x, y, z + 1),
+                ];
+
+                for (face, neighbour) in Face::ALL.into_iter().zip(neighbours) {
+                    if !vis.visible(&neighbour) {
+                        continue;
+                    }
+                    let ao = face_ao(IVec3::new(x, y, z), face, solid);
+                    add_face(&mut mesh, face, offset, Vec3::splat(scale), uvs, ao, layer)?;
+                }
+            }
+        }
+    }
+
+    Ok(mesh.into_mesh())
+}
+
+/// Bounds of a whole chunk mesh, relative to the chunk.
+pub fn chunk_aabb() -> Aabb {
+    Aabb::from_min_max(Vec3::splat(-0.5), Vec3::splat(CHUNK_SIZE as f32 - 0.5))
+}
@@ -1,8 +1,9 @@
-use super::temp_mesh::TempMesh;
+use super::{data::cube::Face, packed_mesh::MAX_AO, temp_mesh::MeshBuilder};
 
 use super::*;
+use crate::dwarf_map::{tile_atlas::TileRenderMode, MapError};
 
-#[derive(Debug, Clone, Copy, Default, Reflect)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Reflect)]
 pub enum TileVisibility {
     #[default]
     Empty,
@@ -21,53 +22,195 @@ impl TileVisibility {
 }
 use TileVisibility::*;
 
+/// Tile index and vertex ambient occlusion of the visible faces of one direction in a layer
+type FaceGrid = [[Option<(usize, [u32; 4])>; CHUNK_SIZE]; CHUNK_SIZE];
+
+/// Ambient occlusion of the four vertices of a face of the tile at `pos`,
+/// from the tiles touching each vertex in front of the face.
+pub fn face_ao(pos: IVec3, face: Face, solid: impl Fn(IVec3) -> bool) -> [u32; 4] {
+    let normal = face.normal();
+    let (t1, t2) = match normal.abs() {
+        IVec3::X => (IVec3::Y, IVec3::Z),
+        IVec3::Y => (IVec3::X, IVec3::Z),
+        _ => (IVec3::X, IVec3::Y),
+    };
+    let base = pos + normal;
+
+    face.vertices().map(|vertex| {
+        // direction of the vertex along the face, each component is -1 or 1
+        let corner = (vertex * 2.0).round().as_ivec3();
+        let side1 = solid(base + t1 * corner.dot(t1));
+        let side2 = solid(base + t2 * corner.dot(t2));
+        let diagonal = solid(base + t1 * corner.dot(t1) + t2 * corner.dot(t2));
+        match (side1, side2) {
+            (true, true) => 0,
+            _ => MAX_AO - side1 as u32 - side2 as u32 - diagonal as u32,
+        }
+    })
+}
+
 /// turn any type that implements [`MeshLayer`] into a mesh, given the layer above and below it
-/// return the FloorWallMesh and the CeilingMesh
-pub fn generate_mesh(
+/// return the FloorWallMesh and the CeilingMesh.
+/// `get_vis` looks up tiles relative to the chunk, as `(x, layer, z)`
+pub fn generate_mesh<M: MeshBuilder>(
     layer: &[[Tile; CHUNK_SIZE]; CHUNK_SIZE],
-    get_vis: impl Fn(UVec2, usize) -> [TileVisibility; 6],
+    get_vis: impl Fn(IVec3) -> TileVisibility,
     layer_index: usize,
-    atlas: &Res<crate::dwarf_map::tile_atlas::TileAtlas>,
-) -> (Mesh, Mesh) {
-    let mut floor_wall_mesh = TempMesh::new();
-    let mut ceiling_mesh = TempMesh::new();
+    atlas: &crate::dwarf_map::tile_atlas::TileAtlas,
+    mode: TileRenderMode,
+) -> Result<(Mesh, Mesh), MapError> {
+    let mut faces = [[[None; CHUNK_SIZE]; CHUNK_SIZE]; 6];
+    let mut ceilings = [[None; CHUNK_SIZE]; CHUNK_SIZE];
 
-    for x in 0..(CHUNK_SIZE as u32) {
-        for z in 0..(CHUNK_SIZE as u32) {
-            let vis = layer[x as usize][z as usize].visibility;
-            let index = layer[x as usize][z as usize].index;
+    for x in 0..CHUNK_SIZE {
+        for z in 0..CHUNK_SIZE {
+            let tile = layer[x][z];
+            let pos = IVec3::new(x as i32, layer_index as i32, z as i32);
+            let neighbors = Face::ALL.map(|face| get_vis(pos + face.normal()));
+            let solid = |pos: IVec3| get_vis(pos) == Solid;
 
-            let neighbors = get_vis(UVec2::new(x, z), layer_index);
-
-            let offset = Vec3::new(x as f32, 0.0, z as f32);
-
-            if vis.visible(&neighbors[0]) {
-                data::cube::add_ceiling(&mut floor_wall_mesh, &offset, atlas.get_uvs(index))
-            } else if vis.visible(&Empty) {
-                data::cube::add_ceiling(&mut ceiling_mesh, &offset, atlas.get_uvs(index))
+            for ((face, grid), neighbour) in Face::ALL.into_iter().zip(&mut faces).zip(neighbors) {
+                if tile.visibility.visible(&neighbour) {
+                    grid[x][z] = Some((tile.index, face_ao(pos, face, solid)));
+                }
             }
 
-            if vis.visible(&neighbors[1]) {
-                data::cube::add_bottom(&mut floor_wall_mesh, &offset, atlas.get_uvs(index))
+            // ceilings are only drawn while everything above is cut away
+            if !tile.visibility.visible(&neighbors[0]) && tile.visibility.visible(&Empty) {
+                ceilings[x][z] = Some((tile.index, [MAX_AO; 4]));
             }
+        }
+    }
 
-            if vis.visible(&neighbors[2]) {
-                data::cube::add_right(&mut floor_wall_mesh, &offset, atlas.get_uvs(index));
-            }
+    let mut floor_wall_mesh = M::default();
+    let mut ceiling_mesh = M::default();
 
-            if vis.visible(&neighbors[3]) {
-                data::cube::add_left(&mut floor_wall_mesh, &offset, atlas.get_uvs(index));
-            }
+    for (face, grid) in Face::ALL.into_iter().zip(&faces) {
+        add_faces(&mut floor_wall_mesh, face, grid, atlas, mode)?;
+    }
+    add_faces(&mut ceiling_mesh, Face::Top, &ceilings, atlas, mode)?;
 
-            if vis.visible(&neighbors[4]) {
-                data::cube::add_front(&mut floor_wall_mesh, &offset, atlas.get_uvs(index));
-            }
+    Ok((floor_wall_mesh.into_mesh(), ceiling_mesh.into_mesh()))
+}
+
+/// Add the faces of one direction. With [`TileRenderMode::Array`] neighbouring unoccluded faces
+/// of the same tile are merged into one quad and the texture repeats across it,
+/// atlas uvs can't repeat so there every tile gets its own quad.
+fn add_faces<M: MeshBuilder>(
+    mesh: &mut M,
+    face: Face,
+    grid: &FaceGrid,
+    atlas: &crate::dwarf_map::tile_atlas::TileAtlas,
+    mode: TileRenderMode,
+) -> Result<(), MapError> {
+    // side faces are a single layer high, they only grow along the side
+    let (grow_x, grow_z) = match (mode, face) {
+        (TileRenderMode::Atlas, _) => (false, false),
+        (TileRenderMode::Array, Face::Top | Face::Bottom) => (true, true),
+        (TileRenderMode::Array, Face::Right | Face::Left) => (false, true),
+        (TileRenderMode::Array, Face::Front | Face::Back) => (true, false),
+    };
 
-            if vis.visible(&neighbors[5]) {
-                data::cube::add_back(&mut floor_wall_mesh, &offset, atlas.get_uvs(index));
+    let mut grid = *grid;
+    for x in 0..CHUNK_SIZE {
+        for z in 0..CHUNK_SIZE {
+            let Some((index, ao)) = grid[x][z] else {
+                continue;
+            };
+
+            // occlusion is interpolated across the quad, only faces without any can merge
+            let (grow_x, grow_z) = match ao == [MAX_AO; 4] {
+                true => (grow_x, grow_z),
+                false => (false, false),
+            };
+            let mut depth = 1;
+            while grow_z && z + depth < CHUNK_SIZE && grid[x][z + depth] == grid[x][z] {
+                depth += 1;
+            }
+            let mut width = 1;
+            while grow_x
+                && x + width < CHUNK_SIZE
+                && grid[x + width][z..z + depth]
+                    .iter()
+                    .all(|cell| *cell == grid[x][z])
+            {
+                width += 1;
             }
+            for row in &mut grid[x..x + width] {
+                row[z..z + depth].fill(None);
+            }
+
+            let offset = Vec3::new(x as f32, 0.0, z as f32);
+            let size = Vec3::new(width as f32, 1.0, depth as f32);
+            let uvs = atlas.get_face_uvs(index, mode, face.uv_size(size))?;
+            data::cube::add_face(mesh, face, offset, size, uvs, ao, index as u32)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dwarf_map::{
+        chunk::{packed_mesh::PackedMesh, temp_mesh::TempMesh},
+        tile_atlas::TileAtlas,
+    };
+
+    /// A single solid layer with nothing around it
+    fn solid(pos: IVec3) -> bool {
+        pos.y == 0 && pos.xz().cmpge(IVec2::ZERO).all() && pos.xz().cmplt(IVec2::splat(16)).all()
+    }
+
+    fn mesh_solid_layer(mode: TileRenderMode) -> Mesh {
+        let layer = [[Tile::solid(1); CHUNK_SIZE]; CHUNK_SIZE];
+        let get_vis = |pos: IVec3| match solid(pos) {
+            true => Solid,
+            false => Empty,
+        };
+        let atlas = TileAtlas::for_tests(2);
+        let (floor_wall, _) = match mode {
+            TileRenderMode::Atlas => generate_mesh::<TempMesh>(&layer, get_vis, 0, &atlas, mode),
+            TileRenderMode::Array => generate_mesh::<PackedMesh>(&layer, get_vis, 0, &atlas, mode),
         }
+        .unwrap();
+        floor_wall
     }
 
-    (floor_wall_mesh.into_mesh(), ceiling_mesh.into_mesh())
+    #[test]
+    fn array_mode_merges_faces_of_the_same_tile() {
+        // top, bottom and one strip per side
+        assert_eq!(
+            mesh_solid_layer(TileRenderMode::Array).count_vertices(),
+            6 * 4
+        );
+    }
+
+    #[test]
+    fn atlas_mode_keeps_a_quad_per_tile() {
+        let quads = 2 * CHUNK_SIZE * CHUNK_SIZE + 4 * CHUNK_SIZE;
+        assert_eq!(
+            mesh_solid_layer(TileRenderMode::Atlas).count_vertices(),
+            quads * 4
+        );
+    }
+
+    #[test]
+    fn ao_darkens_vertices_next_to_a_wall() {
+        // a wall on the +x side, above the top face
+        let wall = |pos: IVec3| pos == IVec3::new(1, 1, 0);
+        let ao = face_ao(IVec3::ZERO, Face::Top, wall);
+        for (vertex, ao) in Face::Top.vertices().iter().zip(ao) {
+            let expected = match vertex.x > 0.0 {
+                true => MAX_AO - 1,
+                false => MAX_AO,
+            };
+            assert_eq!(ao, expected, "vertex {vertex}");
+        }
+
+        // both sides block the light, the corner no longer matters
+        let corner = |pos: IVec3| pos == IVec3::new(1, 1, 0) || pos == IVec3::new(0, 1, 1);
+        assert!(face_ao(IVec3::ZERO, Face::Top, corner).contains(&0));
+    }
 }
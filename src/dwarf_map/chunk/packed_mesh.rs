@@ -0,0 +1,101 @@
+use bevy::{
+    prelude::*,
+    render::{
+        mesh::{Indices, MeshVertexAttribute, PrimitiveTopology},
+        render_asset::RenderAssetUsages,
+        render_resource::VertexFormat,
+    },
+};
+
+use super::temp_mesh::MeshBuilder;
+use crate::dwarf_map::MapError;
+
+/// Packed corner position, face id and ambient occlusion of a vertex.
+///
+/// | bits  | content                                  |
+/// |-------|------------------------------------------|
+/// | 0-4   | x corner, 0..=16                         |
+/// | 5-9   | y corner, 0..=16                         |
+/// | 10-14 | z corner, 0..=16                         |
+/// | 15-17 | face id, see [`face_id`]                 |
+/// | 18-19 | ambient occlusion, 3 is fully unoccluded |
+pub const ATTRIBUTE_PACKED_VERTEX: MeshVertexAttribute =
+    MeshVertexAttribute::new("PackedVertex", 988_540_918, VertexFormat::Uint32);
+
+/// Index of the texture array layer a vertex samples from.
+pub const ATTRIBUTE_TILE_LAYER: MeshVertexAttribute =
+    MeshVertexAttribute::new("TileLayer", 988_540_917, VertexFormat::Uint32);
+
+pub const MAX_AO: u32 = 3;
+
+/// Maps a face normal to the face id used in the packed format,
+/// the order matches [`Face::ALL`](super::data::cube::Face::ALL).
+pub fn face_id(normal: Vec3) -> u32 {
+    match normal {
+        n if n.y > 0.5 => 0,
+        n if n.y < -0.5 => 1,
+        n if n.x > 0.5 => 2,
+        n if n.x < -0.5 => 3,
+        n if n.z < -0.5 => 4,
+        _ => 5,
+    }
+}
+
+pub fn pack_vertex(corner: UVec3, face: u32, ao: u32) -> u32 {
+    corner.x | corner.y << 5 | corner.z << 10 | face << 15 | ao << 18
+}
+
+/// Compact mesh used with the [`TileArrayMaterial`](crate::dwarf_map::tile_array::TileArrayMaterial),
+/// 8 bytes per vertex instead of the 32 a [`TempMesh`](super::temp_mesh::TempMesh) vertex needs.
+/// Position, normal and uv are decoded in `shaders/tile_array.wgsl`.
+#[derive(Default)]
+pub struct PackedMesh {
+    vertices: Vec<u32>,
+    layers: Vec<u32>,
+    indices: Vec<u32>,
+}
+
+impl MeshBuilder for PackedMesh {
+    fn extend(
+        &mut self,
+        _uv: &[Vec2],
+        normals: &[Vec3],
+        vertices: &[Vec3],
+        ao: &[u32],
+        indices: &[u32],
+        layer: u32,
+    ) -> Result<(), MapError> {
+        // check that input data is valid,
+        if normals.len() != vertices.len() || ao.len() != vertices.len() {
+            return Err(MapError::InvalidMesh);
+        }
+
+        let old_length: u32 = self.vertices.len() as u32;
+
+        self.vertices.extend(
+            vertices
+                .iter()
+                .zip(normals)
+                .zip(ao)
+                .map(|((vertex, normal), ao)| {
+                    // vertices sit on the tile corners, offset by half a tile
+                    let corner = (*vertex + 0.5).round().as_uvec3();
+                    pack_vertex(corner, face_id(*normal), *ao)
+                }),
+        );
+        self.layers
+            .extend(std::iter::repeat_n(layer, vertices.len()));
+        self.indices.extend(indices.iter().map(|i| i + old_length));
+        Ok(())
+    }
+
+    fn into_mesh(self) -> Mesh {
+        Mesh::new(
+            PrimitiveTopology::TriangleList,
+            RenderAssetUsages::RENDER_WORLD,
+        )
+        .with_inserted_attribute(ATTRIBUTE_PACKED_VERTEX, self.vertices)
+        .with_inserted_attribute(ATTRIBUTE_TILE_LAYER, self.layers)
+        .with_inserted_indices(Indices::U32(self.indices))
+    }
+}
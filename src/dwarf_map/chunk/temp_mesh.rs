@@ -6,6 +6,29 @@ use bevy::{
     },
 };
 
+use crate::dwarf_map::MapError;
+
+/// Collects the faces of a layer before they are turned into a [`Mesh`].
+pub trait MeshBuilder: Default {
+    /// Function to add any mesh to this mesh.
+    /// `ao` is the ambient occlusion of each vertex, up to [`MAX_AO`](super::packed_mesh::MAX_AO).
+    /// `layer` is the tile index used by all added vertices.
+    fn extend(
+        &mut self,
+        uv: &[Vec2],
+        normals: &[Vec3],
+        vertices: &[Vec3],
+        ao: &[u32],
+        indices: &[u32],
+        layer: u32,
+    ) -> Result<(), MapError>;
+
+    fn into_mesh(self) -> Mesh;
+}
+
+/// Full precision mesh used with the [`StandardMaterial`] atlas path,
+/// the standard material has no use for the ambient occlusion.
+#[derive(Default)]
 pub struct TempMesh {
     uv: Vec<Vec2>,
     normals: Vec<Vec3>,
@@ -13,21 +36,19 @@ pub struct TempMesh {
     indices: Vec<u32>,
 }
 
-impl TempMesh {
-    pub fn new() -> Self {
-        Self {
-            uv: vec![],
-            normals: vec![],
-            vertices: vec![],
-            indices: vec![],
-        }
-    }
-
-    /// Function to add any mesh to this mesh.
-    pub fn extend(&mut self, uv: &[Vec2], normals: &[Vec3], vertices: &[Vec3], indices: &[u32]) {
+impl MeshBuilder for TempMesh {
+    fn extend(
+        &mut self,
+        uv: &[Vec2],
+        normals: &[Vec3],
+        vertices: &[Vec3],
+        _ao: &[u32],
+        indices: &[u32],
+        _layer: u32,
+    ) -> Result<(), MapError> {
         // check that input data is valid,
         if uv.len() != normals.len() || uv.len() != vertices.len() {
-            panic!("Attempt to insert invalid data into TempMesh!");
+            return Err(MapError::InvalidMesh);
         }
 
         let old_length: u32 = self.uv.len() as u32;
@@ -36,9 +57,10 @@ impl TempMesh {
         self.normals.extend(normals);
         self.vertices.extend(vertices);
         self.indices.extend(indices.iter().map(|i| i + old_length));
+        Ok(())
     }
 
-    pub fn into_mesh(self) -> Mesh {
+    fn into_mesh(self) -> Mesh {
         Mesh::new(
             PrimitiveTopology::TriangleList,
             RenderAssetUsages::RENDER_WORLD,
@@ -0,0 +1,111 @@
+use super::{
+    chunk::{ChunkCord, ChunkData, ChunkLayers, CHUNK_SIZE},
+    tile_to_world, CurrentMapLayer,
+};
+use crate::{camera::MainCamera, prelude::*, settings::Settings};
+use bevy::prelude::*;
+
+/// Hides chunk layers that can't be seen, because an opaque layer
+/// between them and the camera covers the whole chunk.
+/// Frustum culling is done by bevy itself, based on the [`Aabb`](bevy::render::primitives::Aabb) of the layer meshes.
+/// Whole chunks beyond the render distance of the [`Settings`] are hidden as well.
+pub struct LayerCullingPlugin;
+
+impl Plugin for LayerCullingPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (
+                cull_occluded_layers.after(super::chunk::update_chunk_meshes),
+                hide_distant_chunks,
+            )
+                .run_if(in_world),
+        );
+    }
+}
+
+/// Which layers of a chunk are completely solid.
+#[derive(Debug, Component, Clone, Copy)]
+pub struct LayerOcclusion {
+    solid: [bool; CHUNK_SIZE],
+}
+
+impl LayerOcclusion {
+    pub fn new(chunk: &ChunkData) -> Self {
+        Self {
+            solid: chunk.solid_layers(),
+        }
+    }
+}
+
+fn cull_occluded_layers(
+    layer: Res<CurrentMapLayer>,
+    cameras: Query<(&GlobalTransform, &Projection), With<MainCamera>>,
+    chunks: Query<(&ChunkCord, &ChunkLayers, &LayerOcclusion)>,
+    mut layer_vis: Query<&mut Visibility, With<super::chunk::ChunkLayer>>,
+) {
+    let Ok((camera, projection)) = cameras.get_single() else {
+        return;
+    };
+    let eye = camera.translation();
+    let looking_down = camera.forward().y < -0.999;
+    let orthographic = matches!(projection, Projection::Orthographic(_));
+
+    for (cord, layers, occlusion) in chunks.iter() {
+        let base = cord.y as usize * CHUNK_SIZE;
+        let min = (cord.xz() * CHUNK_SIZE as u32).as_vec2() - 0.5;
+        let max = min + CHUNK_SIZE as f32;
+
+        // A solid layer only hides what is below it if every ray from the camera to the chunk
+        // passes through it. That is the case for a camera above it and inside the chunk
+        // footprint, or for an orthographic camera looking straight down.
+        let inside = eye.xz().cmpge(min).all() && eye.xz().cmple(max).all();
+        let parallel = orthographic && looking_down;
+
+        // highest layer that hides everything below it, layers above the current one are not rendered
+        let occluder = (0..CHUNK_SIZE)
+            .rev()
+            .filter(|i| base + i <= layer.0 && occlusion.solid[*i])
+            .find(|i| {
+                let top = tile_to_world(UVec3::new(0, (base + i) as u32, 0)).y + 0.5;
+                parallel || (inside && eye.y > top)
+            });
+
+        for (i, entity) in layers.iter().enumerate() {
+            let Ok(mut vis) = layer_vis.get_mut(*entity) else {
+                continue;
+            };
+            let new_vis = match occluder {
+                Some(occluder) if i < occluder => Visibility::Hidden,
+                _ => Visibility::Inherited,
+            };
+            if *vis != new_vis {
+                *vis = new_vis;
+            }
+        }
+    }
+}
+
+fn hide_distant_chunks(
+    settings: Res<Settings>,
+    cameras: Query<&GlobalTransform, With<MainCamera>>,
+    mut chunks: Query<(&ChunkCord, &mut Visibility)>,
+) {
+    let Ok(camera) = cameras.get_single() else {
+        return;
+    };
+    let eye = camera.translation();
+    let max_distance = (settings.render_distance as usize * CHUNK_SIZE) as f32;
+
+    for (cord, mut vis) in chunks.iter_mut() {
+        let center =
+            tile_to_world(cord.0 * CHUNK_SIZE as u32) + Vec3::splat(CHUNK_SIZE as f32 / 2.0);
+        let new_vis = match center.distance(eye) > max_distance {
+            true => Visibility::Hidden,
+            false => Visibility::Inherited,
+        };
+        if *vis != new_vis {
+            *vis = new_vis;
+        }
+    }
+}
@@ -0,0 +1,81 @@
+use super::{chunk::ChunkCache, chunk::CHUNK_SIZE, tile_to_world, world_to_tile, CurrentMapLayer};
+use crate::{camera::MainCamera, prelude::*};
+use bevy::{prelude::*, window::PrimaryWindow};
+
+/// Tracks the tile under the mouse cursor on the [`CurrentMapLayer`].
+/// Lives outside of the cameras, so it survives switching between view modes.
+pub struct TileCursorPlugin;
+
+impl Plugin for TileCursorPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<TileCursor>()
+            .register_type::<TileCursor>()
+            .add_systems(
+                Update,
+                (update_tile_cursor, draw_tile_cursor)
+                    .chain()
+                    .run_if(in_world),
+            );
+    }
+}
+
+#[derive(Debug, Resource, Reflect, Default)]
+pub struct TileCursor {
+    /// Tile under the mouse, if it is inside a loaded chunk.
+    pub hovered: Option<UVec3>,
+    /// Last tile that was clicked.
+    pub selected: Option<UVec3>,
+}
+
+fn update_tile_cursor(
+    mut cursor: ResMut<TileCursor>,
+    layer: Res<CurrentMapLayer>,
+    cache: Res<ChunkCache>,
+    mouse: Res<ButtonInput<MouseButton>>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    cameras: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
+) {
+    let (Ok(window), Ok((camera, camera_transform))) = (windows.get_single(), cameras.get_single())
+    else {
+        return;
+    };
+
+    let hovered = window
+        .cursor_position()
+        .and_then(|pos| camera.viewport_to_world(camera_transform, pos))
+        .and_then(|ray| {
+            // the top face of the tiles on the current layer
+            let plane_origin = tile_to_world(UVec3::new(0, layer.0 as u32, 0)) + Vec3::Y * 0.5;
+            let distance = ray.intersect_plane(plane_origin, Plane3d::new(Vec3::Y))?;
+            // nudge into the tile, so the rounding doesn't pick the layer above
+            world_to_tile(ray.get_point(distance) - Vec3::Y * 0.5)
+        })
+        .filter(|tile| {
+            cache
+                .get(&(*tile / UVec3::splat(CHUNK_SIZE as u32)))
+                .is_some()
+        });
+
+    if cursor.hovered != hovered {
+        cursor.hovered = hovered;
+    }
+
+    if mouse.just_pressed(MouseButton::Left) && hovered.is_some() {
+        cursor.selected = hovered;
+    }
+}
+
+fn draw_tile_cursor(cursor: Res<TileCursor>, mut gizmos: Gizmos) {
+    if let Some(tile) = cursor.hovered {
+        gizmos.cuboid(
+            Transform::from_translation(tile_to_world(tile)).with_scale(Vec3::splat(1.02)),
+            Color::WHITE,
+        );
+    }
+    if let Some(tile) = cursor.selected {
+        gizmos.cuboid(
+            Transform::from_translation(tile_to_world(tile)).with_scale(Vec3::splat(1.04)),
+            Color::YELLOW,
+        );
+    }
+}
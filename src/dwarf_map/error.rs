@@ -0,0 +1,32 @@
+use std::fmt;
+
+use bevy::prelude::*;
+
+/// Everything that can go wrong when reading, writing or drawing the map.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MapError {
+    /// No chunk is loaded at the tile position.
+    OutOfBounds(UVec3),
+    /// The chunk is in the [`ChunkCache`](super::chunk::ChunkCache), but has no data yet.
+    UnloadedChunk(UVec3),
+    /// The material of a tile has no texture in the atlas.
+    InvalidTile(usize),
+    /// Mesh attributes of different lengths.
+    InvalidMesh,
+    /// The tile textures couldn't be combined into an atlas.
+    Atlas(String),
+}
+
+impl fmt::Display for MapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MapError::OutOfBounds(pos) => write!(f, "tile {pos} is outside of the loaded chunks"),
+            MapError::UnloadedChunk(cord) => write!(f, "chunk {cord} is not loaded"),
+            MapError::InvalidTile(index) => write!(f, "material {index} is not in the atlas"),
+            MapError::InvalidMesh => write!(f, "mesh attributes don't have the same length"),
+            MapError::Atlas(reason) => write!(f, "failed to build the tile atlas: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for MapError {}
@@ -0,0 +1,187 @@
+//! Setting up the map of a new game or a loaded save, and removing it again when the game is left.
+//!
+//! Chunks are generated a few per frame in [`LoadingState::GeneratingChunks`], so the loading screen
+//! can show the progress. The map only depends on the [`WorldSettings`], a save regenerates it
+//! from those and replays its commands on top.
+
+use bevy::prelude::*;
+
+use super::{
+    chunk::{ChunkBundle, ChunkCache, ChunkCord, ChunkData, CHUNK_SIZE},
+    cursor::TileCursor,
+    history::MapHistory,
+    CurrentMapLayer, TilePos,
+};
+use crate::{
+    items::{stockpile::Stockpile, ItemIndex},
+    prelude::*,
+    simulation::rng::SimRng,
+};
+
+pub struct WorldGenerationPlugin;
+
+impl Plugin for WorldGenerationPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<WorldSettings>()
+            .register_type::<WorldSettings>()
+            .add_systems(OnEnter(GameState::Generating), start_generation)
+            .add_systems(
+                Update,
+                generate_chunks.run_if(in_state(LoadingState::GeneratingChunks)),
+            )
+            .add_systems(
+                OnTransition {
+                    from: GameState::Playing,
+                    to: GameState::MainMenu,
+                },
+                despawn_world,
+            )
+            .add_systems(
+                OnTransition {
+                    from: GameState::Editor,
+                    to: GameState::MainMenu,
+                },
+                despawn_world,
+            );
+    }
+}
+
+/// Everything the map is generated from.
+#[derive(Debug, Resource, Reflect, Clone, Copy, PartialEq, Eq)]
+pub struct WorldSettings {
+    pub seed: u64,
+    /// Size of the map in chunks.
+    pub size: UVec3,
+    /// Column of chunks the dwarves start in, x and z.
+    pub embark: UVec2,
+}
+
+impl Default for WorldSettings {
+    fn default() -> Self {
+        Self {
+            seed: 0,
+            size: UVec3::new(2, 1, 1),
+            embark: UVec2::ZERO,
+        }
+    }
+}
+
+impl WorldSettings {
+    /// Largest map the new game screen offers, in chunks.
+    pub const MAX_SIZE: UVec3 = UVec3::new(8, 4, 8);
+
+    /// Smallest and largest tile of the embark column.
+    pub fn embark_bounds(&self) -> (UVec3, UVec3) {
+        let size = CHUNK_SIZE as u32;
+        let min = UVec3::new(self.embark.x, 0, self.embark.y) * size;
+        let max = min + UVec3::new(size, self.size.y * size, size) - UVec3::ONE;
+        (min, max)
+    }
+}
+
+/// Chunks left to generate, inserted together with the [`Replay`](crate::simulation::replay::Replay)
+/// before entering [`GameState::Generating`].
+#[derive(Debug, Resource)]
+pub struct WorldGeneration {
+    pub world: WorldSettings,
+    /// Run the commands of the replay before the game starts, instead of playing them back in game.
+    pub catch_up: bool,
+    pending: Vec<UVec3>,
+    total: usize,
+}
+
+impl WorldGeneration {
+    pub fn new(world: WorldSettings, catch_up: bool) -> Self {
+        let size = world.size;
+        let pending: Vec<_> = (0..size.y)
+            .flat_map(|y| {
+                (0..size.x).flat_map(move |x| (0..size.z).map(move |z| UVec3::new(x, y, z)))
+            })
+            .collect();
+        Self {
+            world,
+            catch_up,
+            total: pending.len(),
+            pending,
+        }
+    }
+
+    pub fn generated(&self) -> usize {
+        self.total - self.pending.len()
+    }
+
+    pub fn total(&self) -> usize {
+        self.total
+    }
+}
+
+/// Chunks generated per frame.
+const CHUNKS_PER_FRAME: usize = 4;
+
+fn start_generation(
+    mut commands: Commands,
+    generation: Option<Res<WorldGeneration>>,
+    mut game_state: ResMut<NextState<GameState>>,
+    mut loading: ResMut<NextState<LoadingState>>,
+) {
+    let Some(generation) = generation else {
+        error!("Entered world generation without a world to generate");
+        game_state.set(GameState::MainMenu);
+        return;
+    };
+    info!(
+        "Generating world with seed {}, {} chunks",
+        generation.world.seed, generation.world.size
+    );
+    commands.insert_resource(generation.world);
+    loading.set(LoadingState::GeneratingChunks);
+}
+
+fn generate_chunks(
+    mut commands: Commands,
+    mut generation: ResMut<WorldGeneration>,
+    mut cache: ResMut<ChunkCache>,
+    rng: Res<SimRng>,
+    mut game_state: ResMut<NextState<GameState>>,
+    mut loading: ResMut<NextState<LoadingState>>,
+) {
+    let count = generation.pending.len().min(CHUNKS_PER_FRAME);
+    for cord in generation.pending.drain(..count) {
+        let e = commands
+            .spawn(ChunkBundle {
+                chunk: ChunkData::random(&mut rng.for_chunk(cord)),
+                transform: Transform::from_translation((cord * CHUNK_SIZE as u32).as_vec3()),
+                cord: ChunkCord(cord),
+                ..default()
+            })
+            .id();
+        cache.insert(cord, e);
+    }
+
+    if generation.pending.is_empty() {
+        match generation.catch_up {
+            true => loading.set(LoadingState::ReplayingSave),
+            false => {
+                game_state.set(GameState::Playing);
+                loading.set(LoadingState::Done);
+            }
+        }
+    }
+}
+
+/// Removes the chunks and everything placed on the map, so the next game starts from nothing.
+#[allow(clippy::type_complexity)]
+fn despawn_world(
+    mut commands: Commands,
+    entities: Query<Entity, Or<(With<ChunkCord>, With<TilePos>, With<Stockpile>)>>,
+) {
+    for entity in entities.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    commands.insert_resource(ChunkCache::default());
+    commands.insert_resource(MapHistory::default());
+    commands.insert_resource(ItemIndex::default());
+    commands.insert_resource(TileCursor::default());
+    commands.insert_resource(CurrentMapLayer::default());
+    commands.remove_resource::<WorldGeneration>();
+}
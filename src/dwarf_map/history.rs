@@ -0,0 +1,157 @@
+use std::collections::VecDeque;
+
+use super::chunk::{MapCommands, Tile};
+use crate::prelude::*;
+use bevy::{prelude::*, utils::HashMap};
+
+/// Undo and redo for the tiles written through [`MapCommands`] in the editor.
+pub struct MapHistoryPlugin;
+
+impl Plugin for MapHistoryPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<MapHistory>()
+            .add_systems(OnEnter(GameState::Editor), start_recording)
+            .add_systems(OnExit(GameState::Editor), stop_recording)
+            .add_systems(
+                Update,
+                undo_redo_hotkeys.run_if(in_state(GameState::Editor)),
+            );
+    }
+}
+
+/// Transactions kept for undo, older ones are dropped.
+const MAX_UNDO: usize = 256;
+
+#[derive(Debug, Clone, Copy)]
+pub struct TileEdit {
+    pub pos: UVec3,
+    pub old: Tile,
+    pub new: Tile,
+}
+
+/// Edits that are undone and redone together, like all tiles of a box fill.
+#[derive(Debug, Clone, Default)]
+pub struct Transaction {
+    pub name: String,
+    pub edits: Vec<TileEdit>,
+}
+
+/// Only editor writes are recorded, the simulation changes the map too
+/// and undoing across those writes would revert the dwarves' work.
+#[derive(Debug, Resource, Default)]
+pub struct MapHistory {
+    recording: bool,
+    undo: VecDeque<Transaction>,
+    redo: Vec<Transaction>,
+    open: Option<Transaction>,
+    /// Last tile written to each position, including undo and redo, so saves can keep the edits.
+    changed: HashMap<UVec3, Tile>,
+}
+
+impl MapHistory {
+    /// Starts grouping edits, an already open transaction is committed first.
+    pub fn begin(&mut self, name: impl Into<String>) {
+        self.commit();
+        self.open = Some(Transaction {
+            name: name.into(),
+            edits: vec![],
+        });
+    }
+
+    /// Closes the open transaction, empty ones are dropped.
+    pub fn commit(&mut self) {
+        if let Some(transaction) = self.open.take() {
+            self.push(transaction);
+        }
+    }
+
+    /// Writes outside of a transaction are undone one by one.
+    pub(super) fn record(&mut self, edit: TileEdit) {
+        if !self.recording {
+            return;
+        }
+        match &mut self.open {
+            Some(transaction) => transaction.edits.push(edit),
+            None => self.push(Transaction {
+                name: "Set tile".into(),
+                edits: vec![edit],
+            }),
+        }
+        self.redo.clear();
+    }
+
+    pub(super) fn wrote(&mut self, pos: UVec3, tile: Tile) {
+        if self.recording {
+            self.changed.insert(pos, tile);
+        }
+    }
+
+    /// The tiles changed in the editor since the last call, sorted by position.
+    pub fn take_changes(&mut self) -> Vec<(UVec3, Tile)> {
+        let mut changes: Vec<_> = self.changed.drain().collect();
+        changes.sort_unstable_by_key(|(pos, _)| pos.to_array());
+        changes
+    }
+
+    fn push(&mut self, transaction: Transaction) {
+        if transaction.edits.is_empty() {
+            return;
+        }
+        if self.undo.len() == MAX_UNDO {
+            self.undo.pop_front();
+        }
+        self.undo.push_back(transaction);
+    }
+
+    pub(super) fn pop_undo(&mut self) -> Option<Transaction> {
+        self.commit();
+        self.undo.pop_back()
+    }
+
+    pub(super) fn pop_redo(&mut self) -> Option<Transaction> {
+        self.commit();
+        self.redo.pop()
+    }
+
+    pub(super) fn push_undone(&mut self, transaction: Transaction) {
+        self.redo.push(transaction);
+    }
+
+    pub(super) fn push_redone(&mut self, transaction: Transaction) {
+        self.push(transaction);
+    }
+}
+
+/// Once the simulation ran in between older edits can't be undone safely, the history starts over.
+fn start_recording(mut history: ResMut<MapHistory>) {
+    *history = MapHistory {
+        recording: true,
+        ..default()
+    };
+}
+
+fn stop_recording(mut history: ResMut<MapHistory>) {
+    history.recording = false;
+}
+
+/// `Ctrl+Z` undoes, `Ctrl+Y` and `Ctrl+Shift+Z` redo.
+fn undo_redo_hotkeys(keys: Res<ButtonInput<KeyCode>>, mut map: MapCommands) {
+    if !keys.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]) {
+        return;
+    }
+    let shift = keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+
+    let done = match (
+        keys.just_pressed(KeyCode::KeyZ),
+        keys.just_pressed(KeyCode::KeyY),
+    ) {
+        (true, _) if shift => map.redo(),
+        (true, _) => map.undo(),
+        (false, true) => map.redo(),
+        (false, false) => return,
+    };
+    match done {
+        Some(name) => info!("{name}"),
+        None => info!("Nothing to undo or redo"),
+    }
+}
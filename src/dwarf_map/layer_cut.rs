@@ -0,0 +1,221 @@
+use super::{
+    dwarf_map_flags::*,
+    tile_array::{TileArrayMaterial, TileShading},
+    tile_atlas::TileAtlas,
+    CurrentMapLayer,
+};
+use crate::prelude::*;
+use bevy::prelude::*;
+
+/// Dwarf Fortress style cut-away view.
+/// The [`CeilingMesh`] of the current layer is drawn as a hatched cut face
+/// and every layer below it gets darker with depth.
+pub struct LayerCutPlugin;
+
+impl Plugin for LayerCutPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<LayerCutSettings>()
+            .register_type::<LayerCutSettings>()
+            .add_systems(OnExit(GameState::Loading), init_layer_materials)
+            .add_systems(
+                Update,
+                (update_layer_materials, apply_layer_materials)
+                    .chain()
+                    .run_if(in_world),
+            );
+    }
+}
+
+#[derive(Debug, Resource, Reflect)]
+pub struct LayerCutSettings {
+    pub enabled: bool,
+    /// Brightness lost per layer below the current one.
+    pub fade_per_layer: f32,
+    /// Brightness of the deepest layers.
+    pub min_brightness: f32,
+    /// Tint of the cut faces on the current layer.
+    pub cut_tint: Color,
+    /// Strength of the hatching on cut faces, only supported by [`TileArrayMaterial`].
+    pub cut_hatching: f32,
+}
+
+impl Default for LayerCutSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            fade_per_layer: 0.12,
+            min_brightness: 0.2,
+            cut_tint: Color::rgb(0.75, 0.7, 0.65),
+            cut_hatching: 0.35,
+        }
+    }
+}
+
+impl LayerCutSettings {
+    pub fn brightness(&self, depth: usize) -> f32 {
+        match self.enabled {
+            true => (1.0 - depth as f32 * self.fade_per_layer).max(self.min_brightness),
+            false => 1.0,
+        }
+    }
+
+    /// Number of depths that get their own material, everything below shares the last one.
+    pub fn fade_layers(&self) -> usize {
+        if self.fade_per_layer <= 0.0 {
+            return 1;
+        }
+        ((1.0 - self.min_brightness) / self.fade_per_layer)
+            .ceil()
+            .max(0.0) as usize
+            + 1
+    }
+}
+
+/// Materials for every depth below the current layer and for the cut faces,
+/// for both [`TileRenderMode`](super::tile_atlas::TileRenderMode)s.
+#[derive(Resource, Default)]
+pub struct LayerMaterials {
+    pub atlas_depths: Vec<Handle<StandardMaterial>>,
+    pub atlas_cut: Handle<StandardMaterial>,
+    pub array_depths: Vec<Handle<TileArrayMaterial>>,
+    pub array_cut: Handle<TileArrayMaterial>,
+}
+
+impl LayerMaterials {
+    fn atlas_for(&self, depth: usize) -> Handle<StandardMaterial> {
+        self.atlas_depths[depth.min(self.atlas_depths.len() - 1)].clone()
+    }
+
+    fn array_for(&self, depth: usize) -> Handle<TileArrayMaterial> {
+        self.array_depths[depth.min(self.array_depths.len() - 1)].clone()
+    }
+}
+
+fn init_layer_materials(
+    mut commands: Commands,
+    atlas: Res<TileAtlas>,
+    mut std_materials: ResMut<Assets<StandardMaterial>>,
+    mut array_materials: ResMut<Assets<TileArrayMaterial>>,
+) {
+    let std_base = std_materials
+        .get(&atlas.material)
+        .cloned()
+        .unwrap_or_default();
+    let array_base = array_materials.get(&atlas.array_material).cloned();
+
+    commands.insert_resource(LayerMaterials {
+        atlas_depths: vec![atlas.material.clone()],
+        atlas_cut: std_materials.add(std_base),
+        array_depths: vec![atlas.array_material.clone()],
+        array_cut: array_base
+            .map(|m| array_materials.add(m))
+            .unwrap_or_else(|| atlas.array_material.clone()),
+    });
+}
+
+/// Rebuilds the material colors whenever the settings change.
+fn update_layer_materials(
+    settings: Res<LayerCutSettings>,
+    atlas: Res<TileAtlas>,
+    mut layer_materials: ResMut<LayerMaterials>,
+    mut std_materials: ResMut<Assets<StandardMaterial>>,
+    mut array_materials: ResMut<Assets<TileArrayMaterial>>,
+) {
+    if !settings.is_changed() && !layer_materials.is_added() {
+        return;
+    }
+
+    let Some(std_base) = std_materials.get(&atlas.material).cloned() else {
+        return;
+    };
+    let Some(array_base) = array_materials.get(&atlas.array_material).cloned() else {
+        return;
+    };
+
+    let layer_materials = layer_materials.as_mut();
+    let fade_layers = settings.fade_layers();
+
+    // depth 0 stays the unmodified atlas material
+    layer_materials.atlas_depths.truncate(1);
+    layer_materials.array_depths.truncate(1);
+    for depth in 1..fade_layers {
+        let brightness = settings.brightness(depth);
+        layer_materials
+            .atlas_depths
+            .push(std_materials.add(StandardMaterial {
+                base_color: Color::rgb(brightness, brightness, brightness),
+                ..std_base.clone()
+            }));
+        layer_materials
+            .array_depths
+            .push(array_materials.add(TileArrayMaterial {
+                shading: TileShading {
+                    tint: Color::rgb(brightness, brightness, brightness),
+                    hatching: 0.0,
+                },
+                ..array_base.clone()
+            }));
+    }
+
+    let (cut_tint, cut_hatching) = match settings.enabled {
+        true => (settings.cut_tint, settings.cut_hatching),
+        false => (Color::WHITE, 0.0),
+    };
+    if let Some(cut) = std_materials.get_mut(&layer_materials.atlas_cut) {
+        cut.base_color = cut_tint;
+    }
+    if let Some(cut) = array_materials.get_mut(&layer_materials.array_cut) {
+        cut.shading = TileShading {
+            tint: cut_tint,
+            hatching: cut_hatching,
+        };
+    }
+}
+
+type LayerMeshQuery<'w, 's, T, Other> = Query<
+    'w,
+    's,
+    (
+        &'static T,
+        Option<&'static mut Handle<StandardMaterial>>,
+        Option<&'static mut Handle<TileArrayMaterial>>,
+        Ref<'static, Visibility>,
+    ),
+    Without<Other>,
+>;
+
+/// Assigns the depth and cut materials to the layer meshes.
+fn apply_layer_materials(
+    y: Res<CurrentMapLayer>,
+    layer_materials: Res<LayerMaterials>,
+    mut walls: LayerMeshQuery<WallFloorMesh, CeilingMesh>,
+    mut ceilings: LayerMeshQuery<CeilingMesh, WallFloorMesh>,
+) {
+    let changed = y.is_changed() || layer_materials.is_changed();
+
+    for (wall, std_mat, array_mat, vis) in walls.iter_mut() {
+        if !changed && !vis.is_added() {
+            continue;
+        }
+        let depth = y.0.saturating_sub(wall.0);
+        if let Some(mut mat) = std_mat {
+            *mat = layer_materials.atlas_for(depth);
+        }
+        if let Some(mut mat) = array_mat {
+            *mat = layer_materials.array_for(depth);
+        }
+    }
+
+    // only the ceiling of the current layer is visible, it is always the cut face
+    for (_, std_mat, array_mat, vis) in ceilings.iter_mut() {
+        if !changed && !vis.is_added() {
+            continue;
+        }
+        if let Some(mut mat) = std_mat {
+            *mat = layer_materials.atlas_cut.clone();
+        }
+        if let Some(mut mat) = array_mat {
+            *mat = layer_materials.array_cut.clone();
+        }
+    }
+}
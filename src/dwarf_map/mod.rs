@@ -1,45 +1,74 @@
 use bevy::prelude::*;
 use bevy_inspector_egui::quick::ResourceInspectorPlugin;
 
-use self::chunk::{ChunkBundle, ChunkCache, ChunkCord, ChunkData, CHUNK_SIZE};
+use self::chunk::CHUNK_SIZE;
 
 pub mod chunk;
+pub mod culling;
+pub mod cursor;
+mod error;
+pub mod generation;
+pub mod history;
+pub mod layer_cut;
+pub mod prefab;
+pub mod region;
+pub mod tile_array;
 pub mod tile_atlas;
 mod visibility;
+pub mod vox;
+pub use error::MapError;
 
 pub struct DwarfMapPlugin;
 
 impl Plugin for DwarfMapPlugin {
     fn build(&self, app: &mut App) {
         app.init_resource::<CurrentMapLayer>()
+            .register_type::<TilePos>()
             .add_plugins(visibility::LayerVisibilityPlugin)
+            .add_plugins(layer_cut::LayerCutPlugin)
+            .add_plugins(cursor::TileCursorPlugin)
+            .add_plugins(history::MapHistoryPlugin)
+            .add_plugins(prefab::PrefabPlugin)
+            .add_plugins(culling::LayerCullingPlugin)
             .add_plugins(chunk::ChunkRenderPlugin)
-            .add_systems(Startup, spawn_chunk)
-            .add_plugins(ResourceInspectorPlugin::<CurrentMapLayer>::default());
+            .add_plugins(generation::WorldGenerationPlugin)
+            .add_plugins(MaterialPlugin::<tile_array::TileArrayMaterial> {
+                // the default prepass shader can't read the packed vertex format
+                prepass_enabled: false,
+                ..default()
+            })
+            .add_plugins(ResourceInspectorPlugin::<CurrentMapLayer>::default())
+            .add_plugins(ResourceInspectorPlugin::<tile_atlas::TileRenderMode>::default())
+            .add_plugins(ResourceInspectorPlugin::<layer_cut::LayerCutSettings>::default())
+            .add_plugins(ResourceInspectorPlugin::<chunk::lod::LodSettings>::default());
     }
 }
 
-pub fn spawn_chunk(mut commands: Commands, mut cache: ResMut<ChunkCache>) {
-    for x in 0..2 {
-        for y in 0..1 {
-            for z in 0..1 {
-                let cord = ChunkCord(UVec3::new(x, y, z));
-                let e = commands
-                    .spawn(ChunkBundle {
-                        chunk: ChunkData::random(),
-                        transform: Transform::from_xyz(
-                            (x as usize * CHUNK_SIZE) as f32,
-                            (y as usize * CHUNK_SIZE) as f32,
-                            (z as usize * CHUNK_SIZE) as f32,
-                        ),
-                        cord,
-                        ..default()
-                    })
-                    .id();
-
-                cache.insert(cord.0, e);
-            }
-        }
+/// Layer meshes are shifted down by this amount, see [`update_chunk_meshes`](chunk::update_chunk_meshes).
+pub const LAYER_Y_OFFSET: f32 = 8.0;
+
+/// World position of the center of a tile.
+pub fn tile_to_world(pos: UVec3) -> Vec3 {
+    pos.as_vec3() - Vec3::Y * LAYER_Y_OFFSET
+}
+
+/// Tile that contains a world position, [`None`] if it lies below or behind the map origin.
+pub fn world_to_tile(pos: Vec3) -> Option<UVec3> {
+    let tile = (pos + Vec3::Y * LAYER_Y_OFFSET).round();
+    match tile.min_element() < 0.0 {
+        true => None,
+        false => Some(tile.as_uvec3()),
+    }
+}
+
+/// Position of an entity on the tile grid, in world tile coordinates.
+#[derive(Debug, Component, Reflect, Deref, DerefMut, Default, Clone, Copy, PartialEq, Eq, Hash)]
+#[reflect(Component)]
+pub struct TilePos(pub UVec3);
+
+impl TilePos {
+    pub fn chunk(&self) -> UVec3 {
+        self.0 / UVec3::splat(CHUNK_SIZE as u32)
     }
 }
 
@@ -55,9 +84,12 @@ impl Default for CurrentMapLayer {
 pub mod dwarf_map_flags {
     use bevy::{ecs::component::Component, prelude::Deref};
 
+    /// Faces that are visible from open space, shown for every layer up to the current one.
     #[derive(Debug, Component, Deref)]
     pub struct WallFloorMesh(pub usize);
 
+    /// Tops of solid tiles that are covered by another solid tile.
+    /// Only shown on the current layer, where they are drawn as the cut face of the slice.
     #[derive(Debug, Component, Deref)]
     pub struct CeilingMesh(pub usize);
 }
@@ -0,0 +1,446 @@
+//! Predefined structures, like ruins or a starting wagon, stamped into the map.
+//!
+//! Prefabs are plain text `.prefab` files:
+//!
+//! ```text
+//! size 3 2 3
+//! anchor 1 0 1
+//! layer
+//! 000
+//! 0.0
+//! 000
+//! layer
+//! ???
+//! ?.?
+//! ???
+//! spawn 1 1 1 dwarf Urist
+//! spawn 0 1 0 item Log 2
+//! ```
+//!
+//! `size` and `anchor` are in tiles, `layer` starts the next layer from the bottom up,
+//! with one row per z and one character per x. `.` is air, `?` leaves the tile unchanged
+//! and `0`-`9`, `A`-`Z` are materials 0 to 35.
+//!
+//! The [`EmbarkWagon`] is loaded with the tiles and brings the starting dwarves.
+
+use std::fmt;
+
+use bevy::{
+    asset::{io::Reader, AssetLoader, AsyncReadExt, LoadContext},
+    prelude::*,
+    utils::BoxedFuture,
+};
+
+use super::chunk::{MapCommands, Tile};
+use crate::{
+    dwarves::spawn_dwarf,
+    items::{ItemBundle, ItemKind},
+};
+
+pub struct PrefabPlugin;
+
+impl Plugin for PrefabPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<Prefab>()
+            .init_asset_loader::<PrefabLoader>();
+    }
+}
+
+pub const WAGON_PATH: &str = "prefabs/wagon.prefab";
+
+/// The prefab stamped on the embark tile of a new world, loaded from [`WAGON_PATH`].
+#[derive(Debug, Resource)]
+pub struct EmbarkWagon(pub Handle<Prefab>);
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PrefabCell {
+    /// The tile of the map stays as it is.
+    #[default]
+    Keep,
+    Air,
+    Tile(usize),
+}
+
+/// Something spawned with the prefab, at a position relative to the prefab.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PrefabSpawn {
+    Dwarf { name: String },
+    Item { kind: ItemKind, material: usize },
+}
+
+#[derive(Debug, Clone, Default, Asset, TypePath)]
+pub struct Prefab {
+    pub size: UVec3,
+    /// The tile placed on the stamp position.
+    pub anchor: UVec3,
+    /// Layer first, then x and z, the same order the chunks use.
+    pub cells: Vec<PrefabCell>,
+    pub spawns: Vec<(UVec3, PrefabSpawn)>,
+}
+
+/// Rotation around the y axis in quarter turns and mirroring along x, applied before the rotation.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Reflect)]
+pub struct PrefabOrientation {
+    pub quarter_turns: u8,
+    pub mirror: bool,
+}
+
+impl PrefabOrientation {
+    /// Moves a position inside a prefab of `size` to the oriented prefab.
+    pub fn apply(&self, pos: UVec3, size: UVec3) -> UVec3 {
+        let mut pos = pos;
+        let mut size = size;
+        if self.mirror {
+            pos.x = size.x - 1 - pos.x;
+        }
+        for _ in 0..self.quarter_turns % 4 {
+            pos = UVec3::new(size.z - 1 - pos.z, pos.y, pos.x);
+            size = UVec3::new(size.z, size.y, size.x);
+        }
+        pos
+    }
+}
+
+impl Prefab {
+    /// Checks that every tile and item material is one of the `materials` in the atlas.
+    pub fn validate(&self, materials: usize) -> Result<(), PrefabError> {
+        let tiles = self.cells.iter().filter_map(|cell| match cell {
+            PrefabCell::Tile(index) => Some(*index),
+            _ => None,
+        });
+        let items = self.spawns.iter().filter_map(|(_, spawn)| match spawn {
+            PrefabSpawn::Item { material, .. } => Some(*material),
+            _ => None,
+        });
+        match tiles.chain(items).find(|index| *index >= materials) {
+            Some(index) => Err(PrefabError::UnknownMaterial(index)),
+            None => Ok(()),
+        }
+    }
+
+    pub fn cell(&self, pos: UVec3) -> PrefabCell {
+        let index = (pos.y * self.size.x + pos.x) * self.size.z + pos.z;
+        self.cells.get(index as usize).copied().unwrap_or_default()
+    }
+
+    pub fn parse(text: &str) -> Result<Self, PrefabError> {
+        let mut prefab = Prefab::default();
+        let mut layers: Vec<Vec<&str>> = vec![];
+
+        for (number, line) in text.lines().enumerate() {
+            let error = || PrefabError::Syntax(number + 1);
+            let parts: Vec<_> = line.split_whitespace().collect();
+            let int = |i: usize| -> Result<u32, PrefabError> {
+                parts.get(i).and_then(|p| p.parse().ok()).ok_or_else(error)
+            };
+            let tile = |i: usize| -> Result<UVec3, PrefabError> {
+                Ok(UVec3::new(int(i)?, int(i + 1)?, int(i + 2)?))
+            };
+
+            match parts.as_slice() {
+                [] => {}
+                [comment, ..] if comment.starts_with('#') => {}
+                ["size", ..] => prefab.size = tile(1)?,
+                ["anchor", ..] => prefab.anchor = tile(1)?,
+                ["layer"] => layers.push(vec![]),
+                ["spawn", _, _, _, "dwarf", name @ ..] => {
+                    let spawn = PrefabSpawn::Dwarf {
+                        name: name.join(" "),
+                    };
+                    prefab.spawns.push((tile(1)?, spawn));
+                }
+                ["spawn", _, _, _, "item", kind, _] => {
+                    let kind = ItemKind::ALL
+                        .into_iter()
+                        .find(|k| format!("{k:?}") == *kind)
+                        .ok_or_else(error)?;
+                    let spawn = PrefabSpawn::Item {
+                        kind,
+                        material: int(6)? as usize,
+                    };
+                    prefab.spawns.push((tile(1)?, spawn));
+                }
+                [row] => layers.last_mut().ok_or_else(error)?.push(*row),
+                _ => return Err(error()),
+            }
+        }
+
+        let size = prefab.size;
+        if layers.len() != size.y as usize {
+            return Err(PrefabError::WrongSize);
+        }
+        // rows are z, columns are x, but the cells are stored x first
+        for rows in layers {
+            if rows.len() != size.z as usize || rows.iter().any(|r| r.len() != size.x as usize) {
+                return Err(PrefabError::WrongSize);
+            }
+            for x in 0..size.x as usize {
+                for row in &rows {
+                    let cell = match row.as_bytes()[x] {
+                        b'?' => PrefabCell::Keep,
+                        b'.' => PrefabCell::Air,
+                        c @ b'0'..=b'9' => PrefabCell::Tile((c - b'0') as usize),
+                        c @ b'A'..=b'Z' => PrefabCell::Tile((c - b'A') as usize + 10),
+                        c => return Err(PrefabError::UnknownCell(c as char)),
+                    };
+                    prefab.cells.push(cell);
+                }
+            }
+        }
+
+        let inside = |pos: UVec3| pos.cmplt(size).all();
+        if !inside(prefab.anchor) || !prefab.spawns.iter().all(|(pos, _)| inside(*pos)) {
+            return Err(PrefabError::OutOfBounds);
+        }
+        Ok(prefab)
+    }
+}
+
+#[derive(Debug)]
+pub enum PrefabError {
+    Io(std::io::Error),
+    /// Line number of an entry that can't be read.
+    Syntax(usize),
+    UnknownCell(char),
+    /// The layers don't match the size.
+    WrongSize,
+    /// The anchor or a spawn point lies outside the prefab.
+    OutOfBounds,
+    /// A material the tile atlas has no texture for.
+    UnknownMaterial(usize),
+}
+
+impl fmt::Display for PrefabError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PrefabError::Io(err) => write!(f, "{err}"),
+            PrefabError::Syntax(line) => write!(f, "line {line}: invalid entry"),
+            PrefabError::UnknownCell(c) => write!(f, "unknown cell `{c}`"),
+            PrefabError::WrongSize => write!(f, "layers don't match the size"),
+            PrefabError::OutOfBounds => write!(f, "anchor or spawn point outside of the prefab"),
+            PrefabError::UnknownMaterial(index) => {
+                write!(f, "material {index} is not in the atlas")
+            }
+        }
+    }
+}
+
+impl std::error::Error for PrefabError {}
+
+impl From<std::io::Error> for PrefabError {
+    fn from(err: std::io::Error) -> Self {
+        PrefabError::Io(err)
+    }
+}
+
+#[derive(Default)]
+pub struct PrefabLoader;
+
+impl AssetLoader for PrefabLoader {
+    type Asset = Prefab;
+    type Settings = ();
+    type Error = PrefabError;
+
+    fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader,
+        _settings: &'a Self::Settings,
+        _load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<Self::Asset, Self::Error>> {
+        Box::pin(async move {
+            let mut text = String::new();
+            reader.read_to_string(&mut text).await?;
+            Prefab::parse(&text)
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["prefab"]
+    }
+}
+
+impl<'w, 's> MapCommands<'w, 's> {
+    /// Writes the prefab with its anchor on `pos`, cells outside of the loaded chunks are dropped.
+    /// Returns the entities spawned with it, spawn points outside of the map are skipped as well.
+    /// Nothing is written if the prefab uses more than the atlas' `materials`, see [`Prefab::validate`].
+    pub fn stamp_prefab(
+        &mut self,
+        prefab: &Prefab,
+        pos: UVec3,
+        orientation: PrefabOrientation,
+        materials: usize,
+    ) -> Result<Vec<Entity>, PrefabError> {
+        prefab.validate(materials)?;
+        let anchor = orientation.apply(prefab.anchor, prefab.size).as_ivec3();
+        let to_world = |local: UVec3| {
+            let world = pos.as_ivec3() + orientation.apply(local, prefab.size).as_ivec3() - anchor;
+            (world.min_element() >= 0).then(|| world.as_uvec3())
+        };
+
+        for y in 0..prefab.size.y {
+            for x in 0..prefab.size.x {
+                for z in 0..prefab.size.z {
+                    let local = UVec3::new(x, y, z);
+                    let tile = match prefab.cell(local) {
+                        PrefabCell::Keep => continue,
+                        PrefabCell::Air => Tile::EMPTY,
+                        PrefabCell::Tile(index) => Tile::solid(index),
+                    };
+                    let Some(world) = to_world(local) else {
+                        continue;
+                    };
+                    if let Ok(mut commands) = self.get_tile(world) {
+                        commands.set(tile);
+                    }
+                }
+            }
+        }
+
+        let mut spawned = vec![];
+        for (local, spawn) in &prefab.spawns {
+            let Some(world) = to_world(*local).filter(|w| self.contains(*w)) else {
+                continue;
+            };
+            let mut commands = self.commands();
+            let entity = match spawn {
+                PrefabSpawn::Dwarf { name } => spawn_dwarf(&mut commands, name, world),
+                PrefabSpawn::Item { kind, material } => commands
+                    .spawn(ItemBundle::new(*kind, *material, world))
+                    .id(),
+            };
+            spawned.push(entity);
+        }
+        Ok(spawned)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::RunSystemOnce;
+
+    use super::*;
+    use crate::dwarf_map::{
+        chunk::{test_tile, test_world},
+        region,
+    };
+
+    fn orientations() -> impl Iterator<Item = PrefabOrientation> {
+        (0..4).flat_map(|quarter_turns| {
+            [false, true].map(|mirror| PrefabOrientation {
+                quarter_turns,
+                mirror,
+            })
+        })
+    }
+
+    #[test]
+    fn orientations_move_every_cell_to_its_own_place() {
+        let size = UVec3::new(3, 2, 5);
+        for orientation in orientations() {
+            let turned = match orientation.quarter_turns % 2 {
+                0 => size,
+                _ => UVec3::new(size.z, size.y, size.x),
+            };
+            let mut moved: Vec<_> = region::aabb(UVec3::ZERO, size - UVec3::ONE)
+                .map(|pos| orientation.apply(pos, size))
+                .collect();
+            assert!(moved.iter().all(|pos| pos.cmplt(turned).all()));
+            moved.sort_unstable_by_key(|pos| pos.to_array());
+            moved.dedup();
+            assert_eq!(moved.len(), (size.x * size.y * size.z) as usize);
+        }
+    }
+
+    #[test]
+    fn quarter_turns_rotate_x_onto_z() {
+        let size = UVec3::new(3, 1, 2);
+        let turn = |quarter_turns| PrefabOrientation {
+            quarter_turns,
+            mirror: false,
+        };
+        assert_eq!(
+            turn(0).apply(UVec3::new(2, 0, 1), size),
+            UVec3::new(2, 0, 1)
+        );
+        assert_eq!(
+            turn(1).apply(UVec3::new(2, 0, 0), size),
+            UVec3::new(1, 0, 2)
+        );
+        assert_eq!(
+            turn(2).apply(UVec3::new(2, 0, 0), size),
+            UVec3::new(0, 0, 1)
+        );
+        assert_eq!(
+            turn(4).apply(UVec3::new(2, 0, 1), size),
+            UVec3::new(2, 0, 1)
+        );
+
+        let mirrored = PrefabOrientation {
+            quarter_turns: 0,
+            mirror: true,
+        };
+        assert_eq!(
+            mirrored.apply(UVec3::new(0, 0, 1), size),
+            UVec3::new(2, 0, 1)
+        );
+    }
+
+    const BAR: &str = "size 3 1 1\nanchor 0 0 0\nlayer\n123\n";
+
+    #[test]
+    fn stamps_turn_around_the_anchor() {
+        let prefab = Prefab::parse(BAR).unwrap();
+        let pos = UVec3::new(8, 0, 8);
+        for (orientation, step) in [
+            (PrefabOrientation::default(), IVec3::X),
+            (
+                PrefabOrientation {
+                    quarter_turns: 1,
+                    mirror: false,
+                },
+                IVec3::Z,
+            ),
+            (
+                PrefabOrientation {
+                    quarter_turns: 0,
+                    mirror: true,
+                },
+                IVec3::NEG_X,
+            ),
+        ] {
+            let mut world = test_world([UVec3::ZERO]);
+            let stamp = prefab.clone();
+            world
+                .run_system_once(move |mut map: MapCommands| {
+                    map.stamp_prefab(&stamp, pos, orientation, 4)
+                })
+                .unwrap();
+            for material in 1..=3 {
+                let at = pos.as_ivec3() + step * (material as i32 - 1);
+                assert_eq!(
+                    test_tile(&mut world, at.as_uvec3()),
+                    Tile::solid(material),
+                    "{orientation:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn materials_missing_from_the_atlas_are_rejected() {
+        let prefab = Prefab::parse(BAR).unwrap();
+        assert!(prefab.validate(4).is_ok());
+        assert!(matches!(
+            prefab.validate(3),
+            Err(PrefabError::UnknownMaterial(3))
+        ));
+
+        let mut world = test_world([UVec3::ZERO]);
+        let result = world.run_system_once(move |mut map: MapCommands| {
+            map.stamp_prefab(&prefab, UVec3::ZERO, PrefabOrientation::default(), 3)
+                .map(|spawned| spawned.len())
+        });
+        assert!(matches!(result, Err(PrefabError::UnknownMaterial(3))));
+        assert_eq!(test_tile(&mut world, UVec3::ZERO), Tile::EMPTY);
+    }
+}
@@ -0,0 +1,222 @@
+//! Tile positions of common shapes, to read them with [`ChunkCache::tiles`](super::chunk::ChunkCache::tiles)
+//! or [`MapCommands::tiles`](super::chunk::MapCommands::tiles).
+//!
+//! Boxes and spheres are visited chunk by chunk, so each chunk is only resolved once.
+//! Positions below zero are left out, positions in unloaded chunks are not.
+
+use bevy::prelude::*;
+
+use super::chunk::CHUNK_SIZE;
+
+/// All positions in the box between two corners, both included.
+pub fn aabb(a: UVec3, b: UVec3) -> impl Iterator<Item = UVec3> {
+    let (min, max) = (a.min(b), a.max(b));
+    let size = UVec3::splat(CHUNK_SIZE as u32);
+    between(min / size, max / size).flat_map(move |chunk| {
+        let lo = (chunk * size).max(min);
+        let hi = (chunk * size + size - UVec3::ONE).min(max);
+        between(lo, hi)
+    })
+}
+
+/// All positions within `radius` of `center`.
+pub fn sphere(center: UVec3, radius: u32) -> impl Iterator<Item = UVec3> {
+    let r = UVec3::splat(radius);
+    aabb(center.saturating_sub(r), center + r).filter(move |pos| {
+        let d = pos.as_ivec3() - center.as_ivec3();
+        d.length_squared() <= (radius * radius) as i32
+    })
+}
+
+/// Upright cylinder around `center`, as high as it is wide.
+pub fn cylinder(center: UVec3, radius: u32) -> impl Iterator<Item = UVec3> {
+    let r = UVec3::splat(radius);
+    aabb(center.saturating_sub(r), center + r).filter(move |pos| {
+        let d = pos.as_ivec3() - center.as_ivec3();
+        d.x * d.x + d.z * d.z <= (radius * radius) as i32
+    })
+}
+
+/// Layer first, the same order the chunks store their tiles in.
+fn between(min: UVec3, max: UVec3) -> impl Iterator<Item = UVec3> {
+    (min.y..=max.y).flat_map(move |y| {
+        (min.x..=max.x).flat_map(move |x| (min.z..=max.z).map(move |z| UVec3::new(x, y, z)))
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Neighbourhood {
+    /// The 6 tiles sharing a face.
+    Faces,
+    /// The 26 tiles sharing a face, edge or corner.
+    All,
+}
+
+pub fn neighbours(pos: UVec3, kind: Neighbourhood) -> impl Iterator<Item = UVec3> {
+    let offsets = (-1..=1)
+        .flat_map(|y| (-1..=1).flat_map(move |x| (-1..=1).map(move |z| IVec3::new(x, y, z))));
+    offsets
+        .filter(move |offset| match kind {
+            Neighbourhood::Faces => offset.abs().dot(IVec3::ONE) == 1,
+            Neighbourhood::All => *offset != IVec3::ZERO,
+        })
+        .map(move |offset| pos.as_ivec3() + offset)
+        .filter(|pos| pos.min_element() >= 0)
+        .map(|pos| pos.as_uvec3())
+}
+
+/// The tiles on a straight line from `from` to `to`, both included, using 3D Bresenham.
+pub fn line(from: UVec3, to: UVec3) -> Line {
+    let delta = to.as_ivec3() - from.as_ivec3();
+    let step = delta.signum();
+    let delta = delta.abs();
+    let main = match delta {
+        d if d.x >= d.y && d.x >= d.z => 0,
+        d if d.y >= d.z => 1,
+        _ => 2,
+    };
+    let errors = 2 * delta - delta[main];
+    Line {
+        pos: from.as_ivec3(),
+        step,
+        delta,
+        main,
+        errors,
+        remaining: delta[main] as u32 + 1,
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Line {
+    pos: IVec3,
+    step: IVec3,
+    delta: IVec3,
+    /// The axis with the largest distance, it advances every step.
+    main: usize,
+    errors: IVec3,
+    remaining: u32,
+}
+
+impl Iterator for Line {
+    type Item = UVec3;
+
+    fn next(&mut self) -> Option<UVec3> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        let current = self.pos.as_uvec3();
+
+        let main = self.main;
+        for axis in (0..3).filter(|axis| *axis != main) {
+            if self.errors[axis] >= 0 {
+                self.pos[axis] += self.step[axis];
+                self.errors[axis] -= 2 * self.delta[self.main];
+            }
+            self.errors[axis] += 2 * self.delta[axis];
+        }
+        self.pos[self.main] += self.step[self.main];
+
+        Some(current)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining as usize, Some(self.remaining as usize))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::RunSystemOnce;
+
+    use super::*;
+    use crate::dwarf_map::{
+        chunk::{test_world, MapCommands, Tile},
+        MapError,
+    };
+
+    #[test]
+    fn boxes_visit_every_tile_once_chunk_by_chunk() {
+        let (min, max) = (UVec3::new(14, 3, 2), UVec3::new(17, 4, 3));
+        let tiles: Vec<_> = aabb(max, min).collect();
+        assert_eq!(tiles.len(), 4 * 2 * 2);
+        let chunk = |pos: &UVec3| pos.x / CHUNK_SIZE as u32;
+        // all tiles of the first chunk come before those of the second
+        assert!(tiles.windows(2).all(|w| chunk(&w[0]) <= chunk(&w[1])));
+        let mut unique = tiles.clone();
+        unique.sort_unstable_by_key(|pos| pos.to_array());
+        unique.dedup();
+        assert_eq!(unique.len(), tiles.len());
+    }
+
+    #[test]
+    fn lines_neighbours_and_spheres() {
+        let from = UVec3::new(0, 0, 0);
+        let to = UVec3::new(6, 2, 3);
+        let line: Vec<_> = line(from, to).collect();
+        assert_eq!(line.len(), 7);
+        assert_eq!((line[0], line[6]), (from, to));
+
+        let pos = UVec3::new(5, 5, 5);
+        assert_eq!(neighbours(pos, Neighbourhood::Faces).count(), 6);
+        assert_eq!(neighbours(pos, Neighbourhood::All).count(), 26);
+        assert_eq!(neighbours(UVec3::ZERO, Neighbourhood::All).count(), 7);
+
+        assert_eq!(sphere(pos, 1).count(), 7);
+    }
+
+    #[test]
+    fn copy_and_paste_across_a_chunk_border() {
+        let mut world = test_world([UVec3::ZERO, UVec3::X]);
+        // straddles the border between the chunks at x = 16
+        let (min, max) = (UVec3::new(13, 0, 4), UVec3::new(18, 2, 6));
+        let offset = UVec3::new(6, 3, 5);
+        world.run_system_once(move |mut map: MapCommands| {
+            for (i, pos) in aabb(min, max).enumerate() {
+                if i % 3 != 0 {
+                    map.get_tile(pos).unwrap().set(Tile::solid(i % 4));
+                }
+            }
+        });
+
+        world.run_system_once(move |mut map: MapCommands| {
+            let copied: Vec<_> = map
+                .tiles(aabb(min, max))
+                .map(|(pos, tile)| (pos, tile.unwrap()))
+                .collect();
+            for (pos, tile) in copied {
+                map.get_tile(pos + offset).unwrap().set(tile);
+            }
+        });
+
+        world.run_system_once(move |map: MapCommands| {
+            let source = map.tiles(aabb(min, max));
+            // the source is visited chunk by chunk, the paste in the same order
+            let pasted = map.tiles(aabb(min, max).map(|pos| pos + offset));
+            let mut count = 0;
+            for ((from, copied), (to, pasted)) in source.zip(pasted) {
+                assert_eq!(to, from + offset);
+                assert_eq!(copied.unwrap(), pasted.unwrap(), "{from} -> {to}");
+                count += 1;
+            }
+            assert_eq!(count, 6 * 3 * 3);
+        });
+    }
+
+    #[test]
+    fn tiles_outside_the_map_are_errors() {
+        let mut world = test_world([UVec3::ZERO, UVec3::X]);
+        world.run_system_once(|mut map: MapCommands| {
+            let outside = UVec3::new(2 * CHUNK_SIZE as u32, 0, 0);
+            assert!(matches!(
+                map.get_tile(outside),
+                Err(MapError::OutOfBounds(pos)) if pos == outside
+            ));
+            let read: Vec<_> = map
+                .tiles(aabb(outside - UVec3::X, outside))
+                .map(|(_, tile)| tile)
+                .collect();
+            assert_eq!(read, [Ok(Tile::EMPTY), Err(MapError::OutOfBounds(outside))]);
+        });
+    }
+}
@@ -0,0 +1,179 @@
+use bevy::{
+    pbr::{MaterialPipeline, MaterialPipelineKey},
+    prelude::*,
+    render::{
+        mesh::MeshVertexBufferLayout,
+        render_asset::RenderAssetUsages,
+        render_resource::{
+            AsBindGroup, Extent3d, RenderPipelineDescriptor, ShaderRef,
+            SpecializedMeshPipelineError, TextureDimension, TextureFormat,
+        },
+        texture::{ImageAddressMode, ImageFilterMode, ImageSampler, ImageSamplerDescriptor},
+    },
+};
+
+use super::{
+    chunk::packed_mesh::{ATTRIBUTE_PACKED_VERTEX, ATTRIBUTE_TILE_LAYER},
+    MapError,
+};
+
+/// Material for chunks that sample their tiles from a 2D texture array
+/// instead of a packed atlas. Because every tile owns a whole layer,
+/// UVs can repeat across large faces and mipmaps don't bleed.
+///
+/// Expects meshes in the packed format built by [`PackedMesh`](super::chunk::packed_mesh::PackedMesh).
+#[derive(Asset, TypePath, AsBindGroup, Debug, Clone)]
+pub struct TileArrayMaterial {
+    #[texture(0, dimension = "2d_array")]
+    #[sampler(1)]
+    pub array_texture: Handle<Image>,
+    #[uniform(2)]
+    pub shading: TileShading,
+}
+
+pub use shading::TileShading;
+
+// the `ShaderType` derive emits a check function per field that is never called,
+// the allow has to sit on a module around it
+#[allow(dead_code)]
+mod shading {
+    use bevy::{prelude::*, render::render_resource::ShaderType};
+
+    /// Per material shading, used to fade layers below the current one and mark cut faces.
+    #[derive(Debug, Clone, Copy, ShaderType)]
+    pub struct TileShading {
+        /// Multiplied with the tile color.
+        pub tint: Color,
+        /// Strength of the screen space hatching, 0 disables it.
+        pub hatching: f32,
+    }
+
+    impl Default for TileShading {
+        fn default() -> Self {
+            Self {
+                tint: Color::WHITE,
+                hatching: 0.0,
+            }
+        }
+    }
+}
+
+impl Material for TileArrayMaterial {
+    fn vertex_shader() -> ShaderRef {
+        "shaders/tile_array.wgsl".into()
+    }
+
+    fn fragment_shader() -> ShaderRef {
+        "shaders/tile_array.wgsl".into()
+    }
+
+    fn specialize(
+        _pipeline: &MaterialPipeline<Self>,
+        descriptor: &mut RenderPipelineDescriptor,
+        layout: &MeshVertexBufferLayout,
+        _key: MaterialPipelineKey<Self>,
+    ) -> Result<(), SpecializedMeshPipelineError> {
+        let vertex_layout = layout.get_layout(&[
+            ATTRIBUTE_PACKED_VERTEX.at_shader_location(0),
+            ATTRIBUTE_TILE_LAYER.at_shader_location(1),
+        ])?;
+        descriptor.vertex.buffers = vec![vertex_layout];
+        Ok(())
+    }
+}
+
+/// Stacks the tile images into one array texture with a full mip chain.
+/// Tiles with a different size than the largest one are scaled with nearest filtering.
+pub fn build_tile_array<'a>(tiles: impl IntoIterator<Item = &'a Image>) -> Result<Image, MapError> {
+    let tiles: Vec<Image> = tiles
+        .into_iter()
+        .map(|img| {
+            img.convert(TextureFormat::Rgba8UnormSrgb).ok_or_else(|| {
+                let format = img.texture_descriptor.format;
+                MapError::Atlas(format!(
+                    "tile format {format:?} can't be converted to rgba8"
+                ))
+            })
+        })
+        .collect::<Result<_, _>>()?;
+
+    let size = tiles
+        .iter()
+        .map(|img| img.size())
+        .max_by_key(|size| size.x * size.y)
+        .unwrap_or(UVec2::ONE);
+    let mip_level_count = size.x.min(size.y).ilog2() + 1;
+
+    let mut data = vec![];
+    for tile in &tiles {
+        let mut level = resize_nearest(&tile.data, tile.size(), size);
+        let mut level_size = size;
+        data.extend_from_slice(&level);
+        for _ in 1..mip_level_count {
+            (level, level_size) = downsample(&level, level_size);
+            data.extend_from_slice(&level);
+        }
+    }
+
+    let mut image = Image::new_fill(
+        Extent3d {
+            width: size.x,
+            height: size.y,
+            depth_or_array_layers: tiles.len().max(1) as u32,
+        },
+        TextureDimension::D2,
+        &[255, 0, 255, 255],
+        TextureFormat::Rgba8UnormSrgb,
+        RenderAssetUsages::RENDER_WORLD,
+    );
+    if !tiles.is_empty() {
+        image.data = data;
+        image.texture_descriptor.mip_level_count = mip_level_count;
+    }
+    image.sampler = ImageSampler::Descriptor(ImageSamplerDescriptor {
+        address_mode_u: ImageAddressMode::Repeat,
+        address_mode_v: ImageAddressMode::Repeat,
+        mag_filter: ImageFilterMode::Nearest,
+        min_filter: ImageFilterMode::Linear,
+        mipmap_filter: ImageFilterMode::Linear,
+        ..default()
+    });
+    Ok(image)
+}
+
+fn resize_nearest(data: &[u8], from: UVec2, to: UVec2) -> Vec<u8> {
+    if from == to {
+        return data.to_vec();
+    }
+
+    let mut out = Vec::with_capacity((to.x * to.y * 4) as usize);
+    for y in 0..to.y {
+        let src_y = y * from.y / to.y;
+        for x in 0..to.x {
+            let src_x = x * from.x / to.x;
+            let i = ((src_y * from.x + src_x) * 4) as usize;
+            out.extend_from_slice(&data[i..i + 4]);
+        }
+    }
+    out
+}
+
+/// Halves a rgba8 image with a 2x2 box filter.
+fn downsample(data: &[u8], size: UVec2) -> (Vec<u8>, UVec2) {
+    let new_size = (size / 2).max(UVec2::ONE);
+    let mut out = Vec::with_capacity((new_size.x * new_size.y * 4) as usize);
+    for y in 0..new_size.y {
+        for x in 0..new_size.x {
+            for c in 0..4 {
+                let mut sum = 0u32;
+                for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                    let sx = (x * 2 + dx).min(size.x - 1);
+                    let sy = (y * 2 + dy).min(size.y - 1);
+                    sum += data[((sy * size.x + sx) * 4 + c) as usize] as u32;
+                }
+                out.push((sum / 4) as u8);
+            }
+        }
+    }
+    (out, new_size)
+}
@@ -1,24 +1,88 @@
 use bevy::prelude::*;
 
+use super::{tile_array::TileArrayMaterial, MapError};
+
 #[derive(Resource)]
 pub struct TileAtlas {
     pub image: Handle<Image>,
     pub material: Handle<StandardMaterial>,
     pub layout: TextureAtlasLayout,
+    pub array_image: Handle<Image>,
+    pub array_material: Handle<TileArrayMaterial>,
+}
+
+/// Selects how chunk meshes get their tile textures.
+#[derive(Debug, Resource, Reflect, Default, Clone, Copy, PartialEq, Eq)]
+pub enum TileRenderMode {
+    /// UVs point into a sub rectangle of the packed [`TileAtlas::image`].
+    #[default]
+    Atlas,
+    /// Packed vertices, a per vertex layer selects the tile in [`TileAtlas::array_image`].
+    Array,
 }
 
 impl TileAtlas {
-    pub fn get_uvs(&self, index: usize) -> [Vec2; 4] {
-        let rect = self.layout.textures[index];
+    pub fn get_uvs(&self, index: usize) -> Result<[Vec2; 4], MapError> {
+        let rect = *self
+            .layout
+            .textures
+            .get(index)
+            .ok_or(MapError::InvalidTile(index))?;
 
         let min = rect.min;
         let max = rect.max;
 
-        [
+        Ok([
             min / self.layout.size,
             Vec2::new(min.x, max.y) / self.layout.size,
             max / self.layout.size,
             Vec2::new(max.x, min.y) / self.layout.size,
+        ])
+    }
+
+    /// UVs for a face of the given size in tiles, repeating the tile once per tile.
+    pub fn get_array_uvs(&self, size: Vec2) -> [Vec2; 4] {
+        [
+            Vec2::ZERO,
+            Vec2::new(0.0, size.y),
+            size,
+            Vec2::new(size.x, 0.0),
         ]
     }
+
+    /// UVs for a face of the given size in tiles. The atlas can't repeat a tile, so
+    /// there the tile is stretched over the face; only unmerged faces use it.
+    pub fn get_face_uvs(
+        &self,
+        index: usize,
+        mode: TileRenderMode,
+        size: Vec2,
+    ) -> Result<[Vec2; 4], MapError> {
+        match mode {
+            TileRenderMode::Atlas => self.get_uvs(index),
+            TileRenderMode::Array if index < self.layout.textures.len() => {
+                Ok(self.get_array_uvs(size))
+            }
+            TileRenderMode::Array => Err(MapError::InvalidTile(index)),
+        }
+    }
+}
+
+#[cfg(test)]
+impl TileAtlas {
+    /// An atlas of `tiles` 16x16 tiles in a row, without any images behind it.
+    pub fn for_tests(tiles: usize) -> Self {
+        let mut layout = TextureAtlasLayout::new_empty(Vec2::new(16.0 * tiles as f32, 16.0));
+        for i in 0..tiles {
+            let min = Vec2::new(16.0 * i as f32, 0.0);
+            layout.add_texture(Rect::from_corners(min, min + 16.0));
+        }
+        TileAtlas {
+            image: Handle::default(),
+            material: Handle::default(),
+            layout,
+            array_image: Handle::default(),
+            array_material: Handle::default(),
+        }
+    }
 }
@@ -10,7 +10,7 @@ impl Plugin for LayerVisibilityPlugin {
     fn build(&self, app: &mut App) {
         app.add_systems(
             Update,
-            (set_ceiling_visibility, set_floor_visibility).run_if(in_state(GameState::Playing)),
+            (set_ceiling_visibility, set_floor_visibility).run_if(in_world),
         );
     }
 }
@@ -0,0 +1,405 @@
+//! Reading and writing [MagicaVoxel](https://github.com/ephtracy/voxel-model/blob/master/MagicaVoxel-file-format-vox.txt) `.vox` files.
+//!
+//! The color index of a voxel is the tile material plus one, index 0 is empty in the format.
+//! Models from elsewhere use their own palette, on import each color becomes the material with the closest tile color.
+//! MagicaVoxel has z up, the map has y up, so the axes get swapped on the way in and out.
+
+use std::fmt;
+
+use bevy::prelude::*;
+
+use super::{
+    chunk::{MapCommands, Tile},
+    error::MapError,
+    region,
+    tile_atlas::TileAtlas,
+};
+
+const VERSION: u32 = 150;
+/// Largest model MagicaVoxel opens, along every axis.
+pub const MAX_SIZE: u32 = 256;
+
+#[derive(Debug)]
+pub enum VoxError {
+    Io(std::io::Error),
+    NotVox,
+    /// A chunk ends before its content does.
+    Truncated,
+    /// The file has no `SIZE` or `XYZI` chunk.
+    NoModel,
+    TooLarge(UVec3),
+    /// Materials above 254 don't fit into the palette.
+    UnsupportedMaterial(usize),
+}
+
+impl fmt::Display for VoxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VoxError::Io(err) => write!(f, "{err}"),
+            VoxError::NotVox => write!(f, "not a .vox file"),
+            VoxError::Truncated => write!(f, "file is truncated"),
+            VoxError::NoModel => write!(f, "file contains no model"),
+            VoxError::TooLarge(size) => write!(f, "{size} is larger than {MAX_SIZE} on one axis"),
+            VoxError::UnsupportedMaterial(index) => {
+                write!(f, "material {index} has no color index")
+            }
+        }
+    }
+}
+
+impl From<std::io::Error> for VoxError {
+    fn from(err: std::io::Error) -> Self {
+        VoxError::Io(err)
+    }
+}
+
+/// A single model, positions are in map axes relative to the model origin.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VoxModel {
+    pub size: UVec3,
+    /// Position and color index of every solid voxel.
+    pub voxels: Vec<(UVec3, u8)>,
+    /// Colors for the color indices starting at 1, missing ones are written as grey.
+    pub palette: Vec<[u8; 4]>,
+}
+
+impl VoxModel {
+    /// Reads the tiles from `min` to `max` inclusive, tiles outside of loaded chunks count as empty.
+    pub fn from_region(
+        min: UVec3,
+        max: UVec3,
+        mut get: impl FnMut(UVec3) -> Option<Tile>,
+    ) -> Result<Self, VoxError> {
+        let size = max - min + UVec3::ONE;
+        if size.max_element() > MAX_SIZE {
+            return Err(VoxError::TooLarge(size));
+        }
+
+        let mut voxels = vec![];
+        for y in 0..size.y {
+            for x in 0..size.x {
+                for z in 0..size.z {
+                    let offset = UVec3::new(x, y, z);
+                    let Some(tile) = get(min + offset).filter(Tile::is_solid) else {
+                        continue;
+                    };
+                    let color = u8::try_from(tile.index() + 1)
+                        .map_err(|_| VoxError::UnsupportedMaterial(tile.index()))?;
+                    voxels.push((offset, color));
+                }
+            }
+        }
+
+        Ok(Self {
+            size,
+            voxels,
+            palette: vec![],
+        })
+    }
+
+    /// The material for a color index. With a palette the color is matched to the closest of `tile_colors`,
+    /// see [`atlas_palette`], preferring the material the index stands for on ties.
+    /// Without one the index is the material, as long as the atlas has it.
+    pub fn material(&self, color: u8, tile_colors: &[[u8; 4]]) -> Result<usize, MapError> {
+        let index = (color as usize).saturating_sub(1);
+        let Some(rgba) = self.palette.get(index) else {
+            return match index < tile_colors.len() {
+                true => Ok(index),
+                false => Err(MapError::InvalidTile(index)),
+            };
+        };
+        let distance = |other: &[u8; 4]| -> u32 {
+            rgba.iter()
+                .zip(other)
+                .take(3)
+                .map(|(a, b)| (*a as i32 - *b as i32).pow(2) as u32)
+                .sum()
+        };
+        tile_colors
+            .iter()
+            .enumerate()
+            .min_by_key(|(i, other)| (distance(other), *i != index, *i))
+            .map(|(i, _)| i)
+            .ok_or(MapError::InvalidTile(index))
+    }
+
+    /// Writes the model with its lowest corner at `origin`. With `clear` the empty voxels
+    /// inside the model bounds are cleared as well, otherwise those tiles are left unchanged.
+    /// Nothing is written if a color has no material, see [`material`](Self::material).
+    pub fn stamp(
+        &self,
+        map: &mut MapCommands,
+        origin: UVec3,
+        clear: bool,
+        tile_colors: &[[u8; 4]],
+    ) -> Result<(), MapError> {
+        let mut materials = [None; 256];
+        for (_, color) in &self.voxels {
+            if materials[*color as usize].is_none() {
+                materials[*color as usize] = Some(self.material(*color, tile_colors)?);
+            }
+        }
+
+        if clear && self.size.min_element() > 0 {
+            for pos in region::aabb(origin, origin + self.size - UVec3::ONE) {
+                if let Ok(mut tile) = map.get_tile(pos) {
+                    tile.set(Tile::EMPTY);
+                }
+            }
+        }
+        for (offset, color) in &self.voxels {
+            if let (Ok(mut tile), Some(material)) =
+                (map.get_tile(origin + *offset), materials[*color as usize])
+            {
+                tile.set(Tile::solid(material));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let to_vox = |v: UVec3| [v.x, v.z, v.y];
+
+        let mut size = vec![];
+        for v in to_vox(self.size) {
+            size.extend(v.to_le_bytes());
+        }
+
+        let mut xyzi = (self.voxels.len() as u32).to_le_bytes().to_vec();
+        for (pos, color) in &self.voxels {
+            xyzi.extend(to_vox(*pos).map(|v| v as u8));
+            xyzi.push(*color);
+        }
+
+        let mut rgba = vec![];
+        for i in 0..256 {
+            rgba.extend(self.palette.get(i).copied().unwrap_or([128, 128, 128, 255]));
+        }
+
+        let mut children = vec![];
+        write_chunk(&mut children, b"SIZE", &size, &[]);
+        write_chunk(&mut children, b"XYZI", &xyzi, &[]);
+        write_chunk(&mut children, b"RGBA", &rgba, &[]);
+
+        let mut out = b"VOX ".to_vec();
+        out.extend(VERSION.to_le_bytes());
+        write_chunk(&mut out, b"MAIN", &[], &children);
+        out
+    }
+
+    /// Reads the first model of the file.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, VoxError> {
+        if bytes.get(0..4) != Some(b"VOX ".as_slice()) {
+            return Err(VoxError::NotVox);
+        }
+        let (id, _, children, _) = read_chunk(bytes.get(8..).ok_or(VoxError::Truncated)?)?;
+        if id != b"MAIN" {
+            return Err(VoxError::NotVox);
+        }
+
+        let mut size = None;
+        let mut voxels = None;
+        let mut palette = vec![];
+        let mut rest = children;
+        while !rest.is_empty() {
+            let (id, content, _, next) = read_chunk(rest)?;
+            rest = next;
+            match id {
+                b"SIZE" if size.is_none() => {
+                    let v = read_u32s::<3>(content)?;
+                    size = Some(UVec3::new(v[0], v[2], v[1]));
+                }
+                b"XYZI" if voxels.is_none() => {
+                    let [count] = read_u32s::<1>(content)?;
+                    let data = content
+                        .get(4..4 + count as usize * 4)
+                        .ok_or(VoxError::Truncated)?;
+                    let list = data
+                        .chunks_exact(4)
+                        .filter(|v| v[3] != 0)
+                        .map(|v| (UVec3::new(v[0] as u32, v[2] as u32, v[1] as u32), v[3]))
+                        .collect::<Vec<_>>();
+                    voxels = Some(list);
+                }
+                b"RGBA" => {
+                    palette = content
+                        .chunks_exact(4)
+                        .map(|c| [c[0], c[1], c[2], c[3]])
+                        .collect();
+                }
+                _ => {}
+            }
+        }
+
+        let (Some(size), Some(voxels)) = (size, voxels) else {
+            return Err(VoxError::NoModel);
+        };
+        Ok(Self {
+            size,
+            voxels,
+            palette,
+        })
+    }
+}
+
+fn write_chunk(out: &mut Vec<u8>, id: &[u8; 4], content: &[u8], children: &[u8]) {
+    out.extend(id);
+    out.extend((content.len() as u32).to_le_bytes());
+    out.extend((children.len() as u32).to_le_bytes());
+    out.extend(content);
+    out.extend(children);
+}
+
+/// Splits off a chunk, returns its id, content, children and everything after it.
+#[allow(clippy::type_complexity)]
+fn read_chunk(bytes: &[u8]) -> Result<(&[u8], &[u8], &[u8], &[u8]), VoxError> {
+    let header = bytes.get(0..12).ok_or(VoxError::Truncated)?;
+    let [content_len, children_len] = read_u32s::<2>(&header[4..])?.map(|v| v as usize);
+    let content_end = 12 + content_len;
+    let end = content_end + children_len;
+    if bytes.len() < end {
+        return Err(VoxError::Truncated);
+    }
+    Ok((
+        &header[0..4],
+        &bytes[12..content_end],
+        &bytes[content_end..end],
+        &bytes[end..],
+    ))
+}
+
+fn read_u32s<const N: usize>(bytes: &[u8]) -> Result<[u32; N], VoxError> {
+    let mut out = [0; N];
+    for (i, v) in out.iter_mut().enumerate() {
+        let b = bytes.get(i * 4..i * 4 + 4).ok_or(VoxError::Truncated)?;
+        *v = u32::from_le_bytes([b[0], b[1], b[2], b[3]]);
+    }
+    Ok(out)
+}
+
+/// Average color of every tile in the atlas, in atlas index order, so it lines up with the color indices.
+pub fn atlas_palette(atlas: &TileAtlas, images: &Assets<Image>) -> Vec<[u8; 4]> {
+    let Some(image) = images.get(&atlas.image) else {
+        return vec![];
+    };
+    let width = image.width() as usize;
+
+    atlas
+        .layout
+        .textures
+        .iter()
+        .map(|rect| {
+            let mut sum = [0u64; 4];
+            let mut count = 0;
+            for y in rect.min.y as usize..rect.max.y as usize {
+                for x in rect.min.x as usize..rect.max.x as usize {
+                    let i = (y * width + x) * 4;
+                    if let Some(pixel) = image.data.get(i..i + 4) {
+                        for (s, p) in sum.iter_mut().zip(pixel) {
+                            *s += *p as u64;
+                        }
+                        count += 1;
+                    }
+                }
+            }
+            sum.map(|s| (s / count.max(1)) as u8)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::RunSystemOnce;
+
+    use super::*;
+    use crate::dwarf_map::chunk::{test_tile, test_world};
+
+    /// Materials 1 and 2 look the same, the color index decides between them.
+    const COLORS: [[u8; 4]; 3] = [[200, 40, 40, 255], [40, 200, 40, 255], [40, 200, 40, 255]];
+
+    #[test]
+    fn region_survives_a_round_trip() {
+        let mut world = test_world([UVec3::ZERO, UVec3::X]);
+        let (min, max) = (UVec3::new(14, 0, 0), UVec3::new(17, 1, 2));
+        let origin = UVec3::new(20, 3, 5);
+        // across the chunk border, and one tile the import clears
+        let tiles = [
+            (UVec3::new(14, 0, 0), 0),
+            (UVec3::new(15, 1, 2), 1),
+            (UVec3::new(17, 0, 1), 2),
+        ];
+        let cleared = origin + UVec3::new(1, 1, 1);
+        world.run_system_once(move |mut map: MapCommands| {
+            for (pos, material) in tiles {
+                map.get_tile(pos).unwrap().set(Tile::solid(material));
+            }
+            map.get_tile(cleared).unwrap().set(Tile::solid(0));
+        });
+
+        let bytes = world.run_system_once(move |mut map: MapCommands| {
+            let mut model =
+                VoxModel::from_region(min, max, |pos| map.get_tile(pos).ok().map(|t| t.get()))
+                    .unwrap();
+            model.palette = COLORS.to_vec();
+            model.to_bytes()
+        });
+        let model = VoxModel::from_bytes(&bytes).unwrap();
+        assert_eq!(model.size, UVec3::new(4, 2, 3));
+        assert_eq!(model.voxels.len(), tiles.len());
+
+        world
+            .run_system_once(move |mut map: MapCommands| {
+                model.stamp(&mut map, origin, true, &COLORS)
+            })
+            .unwrap();
+        for (pos, material) in tiles {
+            assert_eq!(
+                test_tile(&mut world, pos - min + origin),
+                Tile::solid(material)
+            );
+        }
+        assert_eq!(test_tile(&mut world, cleared), Tile::EMPTY);
+    }
+
+    #[test]
+    fn colors_become_the_closest_material() {
+        let model = VoxModel {
+            palette: vec![[190, 60, 50, 255], [30, 210, 30, 255], [30, 210, 30, 255]],
+            ..default()
+        };
+        assert_eq!(model.material(1, &COLORS), Ok(0));
+        assert_eq!(model.material(2, &COLORS), Ok(1));
+        assert_eq!(model.material(3, &COLORS), Ok(2));
+        assert_eq!(model.material(1, &[]), Err(MapError::InvalidTile(0)));
+
+        // without a palette the color index is the material
+        let model = VoxModel::default();
+        assert_eq!(model.material(3, &COLORS), Ok(2));
+        assert_eq!(model.material(4, &COLORS), Err(MapError::InvalidTile(3)));
+    }
+
+    #[test]
+    fn unknown_materials_are_rejected_before_writing() {
+        let mut world = test_world([UVec3::ZERO, UVec3::X]);
+        let model = VoxModel {
+            size: UVec3::new(2, 1, 1),
+            voxels: vec![(UVec3::ZERO, 1), (UVec3::X, 9)],
+            palette: vec![],
+        };
+        let result = world.run_system_once(move |mut map: MapCommands| {
+            model.stamp(&mut map, UVec3::ZERO, false, &COLORS)
+        });
+        assert_eq!(result, Err(MapError::InvalidTile(8)));
+        assert_eq!(test_tile(&mut world, UVec3::ZERO), Tile::EMPTY);
+    }
+
+    #[test]
+    fn the_last_color_index_is_the_last_material() {
+        let read = |material| {
+            VoxModel::from_region(UVec3::ZERO, UVec3::ZERO, |_| Some(Tile::solid(material)))
+                .map(|model| model.voxels)
+        };
+        assert_eq!(read(254).unwrap(), [(UVec3::ZERO, u8::MAX)]);
+        assert!(matches!(read(255), Err(VoxError::UnsupportedMaterial(255))));
+    }
+}
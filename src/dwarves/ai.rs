@@ -0,0 +1,231 @@
+use bevy::prelude::*;
+
+use super::{path, Attributes, Dwarf, LaborPreferences, Needs, Skills};
+use crate::{
+    buildings::Blueprint,
+    dwarf_map::{
+        chunk::{ChunkCache, ChunkData},
+        TilePos,
+    },
+    prelude::*,
+    simulation::{Simulation, SimulationClock, SimulationSet, TICK_SECONDS},
+};
+
+/// Utility AI, every tick each dwarf scores its options and does the best one.
+pub struct DwarfAiPlugin;
+
+impl Plugin for DwarfAiPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<Activity>()
+            .register_type::<Decision>()
+            .add_systems(
+                Simulation,
+                (choose_activity, perform_activity)
+                    .chain()
+                    .in_set(SimulationSet::Update)
+                    .run_if(in_simulation),
+            );
+    }
+}
+
+#[derive(Debug, Component, Reflect, Default, Clone, Copy, PartialEq)]
+#[reflect(Component)]
+pub enum Activity {
+    #[default]
+    Idle,
+    /// Working on the construction job of a [`Blueprint`].
+    Building(Entity),
+    Eating,
+    Drinking,
+    Sleeping,
+}
+
+/// Scores of the last decision, kept around to be looked at in the inspector.
+#[derive(Debug, Component, Reflect, Default, Clone, Copy)]
+#[reflect(Component)]
+pub struct Decision {
+    pub idle: f32,
+    pub job: f32,
+    pub eat: f32,
+    pub drink: f32,
+    pub sleep: f32,
+    /// Tick the current activity was picked on.
+    pub since_tick: u64,
+}
+
+/// Score of doing nothing, everything below it is not worth the effort.
+const IDLE_SCORE: f32 = 0.1;
+/// Score of a job for a dwarf that likes that labour.
+const JOB_SCORE: f32 = 0.4;
+/// Bonus for the current activity, so dwarves don't flip between two close options every tick.
+const COMMITMENT: f32 = 0.15;
+/// Below this a need counts as satisfied.
+const SATISFIED: f32 = 0.05;
+
+/// Need decrease per tick while taking care of it.
+const EAT_RATE: f32 = 0.05;
+const DRINK_RATE: f32 = 0.08;
+const SLEEP_RATE: f32 = 0.005;
+
+/// Ticks between two steps at agility 1.
+const STEP_TICKS: u32 = 3;
+/// Skill gained per tick of work.
+const SKILL_GAIN: f32 = 0.001;
+
+/// Needs get urgent slowly at first and fast once they are high.
+fn need_score(need: f32) -> f32 {
+    match need < SATISFIED {
+        true => 0.0,
+        false => need * need,
+    }
+}
+
+#[allow(clippy::type_complexity)]
+pub fn choose_activity(
+    clock: Res<SimulationClock>,
+    mut dwarves: Query<(
+        Entity,
+        &Dwarf,
+        &TilePos,
+        &Needs,
+        &LaborPreferences,
+        &mut Activity,
+        &mut Decision,
+    )>,
+    mut blueprints: Query<(Entity, &mut Blueprint)>,
+) {
+    // dwarves compete for jobs, the first one to look takes it
+    let mut order: Vec<_> = dwarves.iter().map(|(e, d, ..)| (d.id, e)).collect();
+    order.sort_unstable();
+
+    for (_, entity) in order {
+        let Ok((dwarf, _, pos, needs, labors, mut activity, mut decision)) =
+            dwarves.get_mut(entity)
+        else {
+            continue;
+        };
+        // the job this dwarf would take, its current one or the closest free one,
+        // blueprints never overlap so their corner decides between equally close ones
+        let job = match *activity {
+            Activity::Building(job) if blueprints.contains(job) => Some(job),
+            _ => blueprints
+                .iter()
+                .filter(|(_, b)| b.is_ready() && b.worker.is_none())
+                .min_by_key(|(_, b)| {
+                    let min = b.footprint.min;
+                    (
+                        min.as_ivec3().distance_squared(pos.as_ivec3()),
+                        min.to_array(),
+                    )
+                })
+                .map(|(e, _)| e),
+        };
+
+        let mut scores = Decision {
+            idle: IDLE_SCORE,
+            job: match (job, labors.construction) {
+                (Some(_), true) => JOB_SCORE,
+                _ => 0.0,
+            },
+            eat: need_score(needs.hunger),
+            drink: need_score(needs.thirst),
+            sleep: need_score(needs.fatigue),
+            since_tick: decision.since_tick,
+        };
+        match *activity {
+            Activity::Idle => scores.idle += COMMITMENT,
+            Activity::Building(_) if scores.job > 0.0 => scores.job += COMMITMENT,
+            Activity::Eating if scores.eat > 0.0 => scores.eat += COMMITMENT,
+            Activity::Drinking if scores.drink > 0.0 => scores.drink += COMMITMENT,
+            Activity::Sleeping if scores.sleep > 0.0 => scores.sleep += COMMITMENT,
+            _ => {}
+        }
+
+        let options = [
+            (scores.idle, Activity::Idle),
+            (scores.job, job.map_or(Activity::Idle, Activity::Building)),
+            (scores.eat, Activity::Eating),
+            (scores.drink, Activity::Drinking),
+            (scores.sleep, Activity::Sleeping),
+        ];
+        // the first option wins ties, so the order above is the priority
+        let next = options
+            .into_iter()
+            .reduce(|best, option| match option.0 > best.0 {
+                true => option,
+                false => best,
+            })
+            .map_or(Activity::Idle, |(_, activity)| activity);
+
+        if *activity != next {
+            if let Activity::Building(old) = *activity {
+                if let Ok((_, mut blueprint)) = blueprints.get_mut(old) {
+                    blueprint.worker = None;
+                }
+            }
+            if let Activity::Building(new) = next {
+                if let Ok((_, mut blueprint)) = blueprints.get_mut(new) {
+                    blueprint.worker = Some(dwarf);
+                }
+            }
+            *activity = next;
+            scores.since_tick = clock.tick;
+        }
+        *decision = scores;
+    }
+}
+
+fn perform_activity(
+    cache: Res<ChunkCache>,
+    chunks: Query<&ChunkData>,
+    mut dwarves: Query<(
+        &mut Dwarf,
+        &mut TilePos,
+        &mut Needs,
+        &mut Skills,
+        &Attributes,
+        &mut Activity,
+    )>,
+    mut blueprints: Query<&mut Blueprint>,
+) {
+    for (mut dwarf, mut pos, mut needs, mut skills, attributes, mut activity) in dwarves.iter_mut()
+    {
+        dwarf.step_cooldown = dwarf.step_cooldown.saturating_sub(1);
+
+        match *activity {
+            Activity::Idle => {}
+            Activity::Eating => needs.hunger = (needs.hunger - EAT_RATE).max(0.0),
+            Activity::Drinking => needs.thirst = (needs.thirst - DRINK_RATE).max(0.0),
+            Activity::Sleeping => {
+                needs.fatigue = (needs.fatigue - SLEEP_RATE * attributes.endurance).max(0.0)
+            }
+            Activity::Building(job) => {
+                let Ok(mut blueprint) = blueprints.get_mut(job) else {
+                    // finished or cancelled
+                    *activity = Activity::Idle;
+                    continue;
+                };
+
+                let footprint = blueprint.footprint;
+                let next_to = |pos: UVec3| {
+                    footprint.tiles().any(|tile| {
+                        let d = tile.as_ivec3() - pos.as_ivec3();
+                        d.y == 0 && d.x.abs() <= 1 && d.z.abs() <= 1
+                    })
+                };
+                if next_to(pos.0) {
+                    let speed = attributes.strength * (1.0 + skills.construction);
+                    blueprint.work_left -= TICK_SECONDS * speed;
+                    skills.construction += SKILL_GAIN;
+                } else if dwarf.step_cooldown == 0 {
+                    // without a path the dwarf keeps the job and waits for one to open up
+                    let open = |tile| path::is_open(&cache, &chunks, tile);
+                    if let Some(step) = path::first_step(pos.0, next_to, open) {
+                        pos.0 = step;
+                    }
+                    dwarf.step_cooldown = (STEP_TICKS as f32 / attributes.agility).round() as u32;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,267 @@
+use bevy::{ecs::system::Command, prelude::*};
+
+use crate::{
+    dwarf_map::{
+        chunk::{MapCommands, Tile},
+        generation::WorldSettings,
+        prefab::{EmbarkWagon, Prefab, PrefabOrientation},
+        region,
+        tile_atlas::TileAtlas,
+        tile_to_world, MapError, TilePos,
+    },
+    prelude::*,
+    simulation::{Simulation, SimulationSet},
+};
+
+pub mod ai;
+pub mod path;
+
+/// Dwarves with needs, skills and labour preferences, driven by [`ai`].
+pub struct DwarfPlugin;
+
+impl Plugin for DwarfPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<Dwarf>()
+            .register_type::<Needs>()
+            .register_type::<Attributes>()
+            .register_type::<Skills>()
+            .register_type::<LaborPreferences>()
+            .add_plugins(ai::DwarfAiPlugin)
+            .add_systems(OnExit(GameState::Loading), init_dwarf_assets)
+            .add_systems(OnExit(LoadingState::GeneratingChunks), embark)
+            .add_systems(
+                Simulation,
+                update_needs
+                    .before(ai::choose_activity)
+                    .in_set(SimulationSet::Update)
+                    .run_if(in_simulation),
+            )
+            .add_systems(
+                Update,
+                (attach_dwarf_visuals, move_dwarves).run_if(in_world),
+            );
+    }
+}
+
+#[derive(Debug, Component, Reflect, Default)]
+#[reflect(Component)]
+pub struct Dwarf {
+    /// Order the dwarf was spawned in, the simulation uses it where entity ids would
+    /// break ties differently in every run.
+    pub id: u32,
+    /// Ticks until the next step to another tile.
+    pub step_cooldown: u32,
+}
+
+/// How urgent each need is, `0.0` is satisfied and `1.0` is desperate.
+#[derive(Debug, Component, Reflect, Default, Clone, Copy)]
+#[reflect(Component)]
+pub struct Needs {
+    pub hunger: f32,
+    pub thirst: f32,
+    pub fatigue: f32,
+}
+
+/// Need increase per tick.
+const HUNGER_RATE: f32 = 0.0005;
+const THIRST_RATE: f32 = 0.0008;
+const FATIGUE_RATE: f32 = 0.0003;
+
+/// Inborn traits, `1.0` is average.
+#[derive(Debug, Component, Reflect, Clone, Copy)]
+#[reflect(Component)]
+pub struct Attributes {
+    /// Scales construction speed.
+    pub strength: f32,
+    /// Scales walking speed.
+    pub agility: f32,
+    /// Scales how fast a dwarf gets tired.
+    pub endurance: f32,
+}
+
+impl Default for Attributes {
+    fn default() -> Self {
+        Self {
+            strength: 1.0,
+            agility: 1.0,
+            endurance: 1.0,
+        }
+    }
+}
+
+/// Learned skills, grow with each tick of work.
+#[derive(Debug, Component, Reflect, Default, Clone, Copy)]
+#[reflect(Component)]
+pub struct Skills {
+    pub construction: f32,
+}
+
+/// Labours a dwarf is willing to do.
+#[derive(Debug, Component, Reflect, Clone, Copy)]
+#[reflect(Component)]
+pub struct LaborPreferences {
+    pub construction: bool,
+}
+
+impl Default for LaborPreferences {
+    fn default() -> Self {
+        Self { construction: true }
+    }
+}
+
+#[derive(Bundle)]
+pub struct DwarfBundle {
+    pub dwarf: Dwarf,
+    pub pos: TilePos,
+    pub needs: Needs,
+    pub attributes: Attributes,
+    pub skills: Skills,
+    pub labors: LaborPreferences,
+    pub activity: ai::Activity,
+    pub decision: ai::Decision,
+    pub name: Name,
+}
+
+impl DwarfBundle {
+    pub fn new(id: u32, name: &str, pos: UVec3) -> Self {
+        Self {
+            dwarf: Dwarf { id, ..default() },
+            pos: TilePos(pos),
+            needs: Needs::default(),
+            attributes: Attributes::default(),
+            skills: Skills::default(),
+            labors: LaborPreferences::default(),
+            activity: ai::Activity::default(),
+            decision: ai::Decision::default(),
+            name: Name::new(name.to_string()),
+        }
+    }
+}
+
+/// Spawns a dwarf with the next free [`Dwarf::id`].
+pub fn spawn_dwarf(commands: &mut Commands, name: &str, pos: UVec3) -> Entity {
+    let entity = commands.spawn_empty().id();
+    commands.add(SpawnDwarf {
+        entity,
+        name: name.to_string(),
+        pos,
+    });
+    entity
+}
+
+/// The id is picked when the command is applied, so dwarves spawned by the same system
+/// are numbered in the order they were sent.
+struct SpawnDwarf {
+    entity: Entity,
+    name: String,
+    pos: UVec3,
+}
+
+impl Command for SpawnDwarf {
+    fn apply(self, world: &mut World) {
+        let id = world
+            .query::<&Dwarf>()
+            .iter(world)
+            .map(|dwarf| dwarf.id + 1)
+            .max()
+            .unwrap_or(0);
+        if let Some(mut entity) = world.get_entity_mut(self.entity) {
+            entity.insert(DwarfBundle::new(id, &self.name, self.pos));
+        }
+    }
+}
+
+/// The starting dwarves if there is no [`EmbarkWagon`] to bring them.
+const NAMES: [&str; 3] = ["Urist", "Kogan", "Domas"];
+const DWARF_HEIGHT: f32 = 0.8;
+
+/// Stamps the [`EmbarkWagon`] with its anchor on the standable tile closest to the center
+/// of the embark column, the dwarves it brings are the starting dwarves.
+/// Without a standable tile it lands on top of the column, without a wagon the [`NAMES`] start there on their own.
+fn embark(
+    mut map: MapCommands,
+    world: Res<WorldSettings>,
+    wagon: Option<Res<EmbarkWagon>>,
+    prefabs: Res<Assets<Prefab>>,
+    atlas: Res<TileAtlas>,
+) {
+    let (min, max) = world.embark_bounds();
+    let center = (min + max) / 2;
+
+    let standable = |(pos, here): &(UVec3, Result<Tile, MapError>)| {
+        let below = map.tiles([*pos - UVec3::Y]).next().map(|(_, tile)| tile);
+        matches!((here, below), (Ok(here), Some(Ok(below))) if !here.is_solid() && below.is_solid())
+    };
+    let start = map
+        .tiles(region::aabb(min + UVec3::Y, max))
+        .filter(standable)
+        .map(|(pos, _)| pos)
+        .min_by_key(|pos| pos.as_ivec3().distance_squared(center.as_ivec3()))
+        .unwrap_or(UVec3::new(center.x, max.y + 1, center.z));
+
+    if let Some(wagon) = wagon.and_then(|wagon| prefabs.get(&wagon.0)) {
+        let materials = atlas.layout.textures.len();
+        match map.stamp_prefab(wagon, start, PrefabOrientation::default(), materials) {
+            Ok(_) => return,
+            Err(err) => error!("Failed to stamp the embark wagon: {err}"),
+        }
+    }
+    for (i, name) in NAMES.iter().enumerate() {
+        spawn_dwarf(&mut map.commands(), name, start + UVec3::X * i as u32);
+    }
+}
+
+#[derive(Resource)]
+struct DwarfAssets {
+    mesh: Handle<Mesh>,
+    material: Handle<StandardMaterial>,
+}
+
+fn init_dwarf_assets(
+    mut commands: Commands,
+    mut mesh_assets: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    commands.insert_resource(DwarfAssets {
+        mesh: mesh_assets.add(Capsule3d::new(0.25, DWARF_HEIGHT - 0.5)),
+        material: materials.add(Color::rgb(0.8, 0.4, 0.2)),
+    });
+}
+
+/// Every dwarf gets drawn, no matter if it came with the embark, a prefab or a save.
+fn attach_dwarf_visuals(
+    mut commands: Commands,
+    assets: Res<DwarfAssets>,
+    dwarves: Query<(Entity, &TilePos), Added<Dwarf>>,
+) {
+    for (entity, pos) in dwarves.iter() {
+        commands.entity(entity).insert(PbrBundle {
+            mesh: assets.mesh.clone(),
+            material: assets.material.clone(),
+            transform: dwarf_transform(pos.0),
+            ..default()
+        });
+    }
+}
+
+fn dwarf_transform(pos: UVec3) -> Transform {
+    // standing on the floor of the tile
+    Transform::from_translation(tile_to_world(pos) - Vec3::Y * (0.5 - DWARF_HEIGHT / 2.0))
+}
+
+fn update_needs(mut dwarves: Query<(&mut Needs, &Attributes, &ai::Activity)>) {
+    for (mut needs, attributes, activity) in dwarves.iter_mut() {
+        needs.hunger = (needs.hunger + HUNGER_RATE).min(1.0);
+        needs.thirst = (needs.thirst + THIRST_RATE).min(1.0);
+        if *activity != ai::Activity::Sleeping {
+            needs.fatigue = (needs.fatigue + FATIGUE_RATE / attributes.endurance).min(1.0);
+        }
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn move_dwarves(mut dwarves: Query<(&TilePos, &mut Transform), (With<Dwarf>, Changed<TilePos>)>) {
+    for (pos, mut transform) in dwarves.iter_mut() {
+        *transform = dwarf_transform(pos.0);
+    }
+}
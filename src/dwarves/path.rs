@@ -0,0 +1,117 @@
+use std::collections::VecDeque;
+
+use bevy::{prelude::*, utils::HashMap};
+
+use crate::dwarf_map::chunk::{ChunkCache, ChunkData};
+
+/// Tiles looked at before a search gives up, walks across the whole embark stay well below it.
+const MAX_SEARCH: usize = 4096;
+
+/// Horizontal directions in the order they are searched, the order decides between equally short paths.
+const DIRECTIONS: [IVec3; 4] = [IVec3::X, IVec3::NEG_X, IVec3::Z, IVec3::NEG_Z];
+
+/// Whether a dwarf can be in a tile, tiles outside of the loaded chunks are open air.
+pub fn is_open(cache: &ChunkCache, chunks: &Query<&ChunkData>, pos: UVec3) -> bool {
+    cache
+        .get_tile(chunks, pos)
+        .map_or(true, |tile| !tile.is_solid())
+}
+
+/// An open tile with solid ground below it.
+fn standable(open: &impl Fn(UVec3) -> bool, pos: UVec3) -> bool {
+    open(pos) && pos.y > 0 && !open(pos - UVec3::Y)
+}
+
+/// The tiles a dwarf can walk to from `pos` in one step: the four horizontal neighbours,
+/// or one tile up or down a slope if there is headroom for the step.
+fn steps(open: &impl Fn(UVec3) -> bool, pos: UVec3) -> impl Iterator<Item = UVec3> + '_ {
+    DIRECTIONS.into_iter().flat_map(move |dir| {
+        [IVec3::ZERO, IVec3::Y, IVec3::NEG_Y]
+            .into_iter()
+            .filter_map(move |dy| {
+                let next = pos.as_ivec3() + dir + dy;
+                if next.cmplt(IVec3::ZERO).any() {
+                    return None;
+                }
+                let next = next.as_uvec3();
+                let headroom = match dy.y {
+                    1 => open(pos + UVec3::Y),
+                    -1 => open(next + UVec3::Y),
+                    _ => true,
+                };
+                (headroom && standable(open, next)).then_some(next)
+            })
+    })
+}
+
+/// Breadth first search for the closest tile accepted by `goal`.
+/// Returns the first step of the path, `None` if `from` already is a goal
+/// or no goal can be reached within [`MAX_SEARCH`] tiles.
+pub fn first_step(
+    from: UVec3,
+    goal: impl Fn(UVec3) -> bool,
+    open: impl Fn(UVec3) -> bool,
+) -> Option<UVec3> {
+    let mut came_from = HashMap::from([(from, from)]);
+    let mut queue = VecDeque::from([from]);
+
+    while let Some(pos) = queue.pop_front() {
+        if goal(pos) {
+            // walk back to the tile next to the start
+            let mut step = pos;
+            while came_from[&step] != from {
+                step = came_from[&step];
+            }
+            return (step != from).then_some(step);
+        }
+        if came_from.len() >= MAX_SEARCH {
+            return None;
+        }
+        for next in steps(&open, pos) {
+            if !came_from.contains_key(&next) {
+                came_from.insert(next, pos);
+                queue.push_back(next);
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A floor at y = 0 with a wall along x = 2, too high to climb, that only has a gap at z = 4.
+    fn open(pos: UVec3) -> bool {
+        let wall = pos.x == 2 && (1..=2).contains(&pos.y) && pos.z != 4;
+        pos.y != 0 && !wall
+    }
+
+    fn walk(from: UVec3, to: UVec3) -> Vec<UVec3> {
+        let mut path = vec![from];
+        while let Some(step) = first_step(*path.last().unwrap(), |pos| pos == to, open) {
+            path.push(step);
+            assert!(path.len() < 32, "no progress towards {to}");
+        }
+        path
+    }
+
+    #[test]
+    fn walks_around_walls() {
+        let path = walk(UVec3::new(0, 1, 0), UVec3::new(4, 1, 0));
+        assert_eq!(path.last(), Some(&UVec3::new(4, 1, 0)));
+        assert!(path.iter().all(|pos| open(*pos)));
+        assert!(path.contains(&UVec3::new(2, 1, 4)));
+        // 6 steps to the gap and 6 back
+        assert_eq!(path.len(), 13);
+    }
+
+    #[test]
+    fn gives_up_without_a_path() {
+        let closed = |pos: UVec3| pos.y == 1 && pos.x < 2 && pos.z < 2;
+        assert_eq!(
+            first_step(UVec3::new(0, 1, 0), |pos| pos.x == 4, closed),
+            None
+        );
+    }
+}
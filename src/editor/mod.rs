@@ -0,0 +1,432 @@
+use std::path::Path;
+
+use bevy::prelude::*;
+use bevy_inspector_egui::{
+    bevy_egui::EguiContexts,
+    egui::{self, load::SizedTexture},
+};
+
+use crate::{
+    dwarf_map::{
+        chunk::{MapCommands, Tile, CHUNK_SIZE},
+        cursor::TileCursor,
+        prefab::{Prefab, PrefabError, PrefabOrientation},
+        region::{self, Neighbourhood},
+        tile_atlas::TileAtlas,
+        tile_to_world,
+        vox::{self, VoxError, VoxModel},
+    },
+    prelude::*,
+    settings::{Action, Settings},
+};
+
+pub mod tools;
+use tools::CarveShape;
+
+/// Map editor, [`Action::ToggleEditor`] (`F2`) switches between the game and [`GameState::Editor`].
+/// All edits go through [`MapCommands`], so they can be undone.
+/// The editor UI lives here and only runs in the editor, the game UI doesn't know about it.
+pub struct EditorPlugin;
+
+impl Plugin for EditorPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<EditorSettings>()
+            .register_type::<EditorSettings>()
+            .add_systems(Update, toggle_editor.run_if(in_world))
+            .add_systems(
+                Update,
+                (editor_ui, apply_tool, draw_tool_preview)
+                    .chain()
+                    .run_if(in_state(GameState::Editor)),
+            );
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Reflect)]
+pub enum EditorTool {
+    /// Paints single tiles while the mouse is held.
+    Brush,
+    /// Two clicks set the corners.
+    BoxFill,
+    /// Replaces the connected tiles with the same material.
+    FloodFill,
+    /// Always removes tiles.
+    Carve(CarveShape),
+    /// Two clicks select a box, for export.
+    Select,
+}
+
+/// File actions requested from the UI, run with the map at hand.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Reflect)]
+pub enum FileAction {
+    ExportVox,
+    ImportVox,
+    /// Chunk meshes around the selection, for debugging the meshing.
+    ExportGltf,
+    StampPrefab,
+}
+
+#[derive(Debug, Resource, Reflect)]
+pub struct EditorSettings {
+    pub tool: EditorTool,
+    /// Index into the [`TileAtlas`].
+    pub material: usize,
+    /// Tools place empty tiles instead of the material.
+    pub erase: bool,
+    pub radius: u32,
+    /// Flood fills also spread over edges and corners.
+    pub flood_diagonal: bool,
+    /// First corner of a box fill or selection.
+    corner: Option<UVec3>,
+    pub selection: Option<(UVec3, UVec3)>,
+    /// File for import and export.
+    pub path: String,
+    /// Imports clear the empty voxels of the model, instead of leaving the tiles unchanged.
+    pub import_clear: bool,
+    pub prefab_path: String,
+    pub prefab_orientation: PrefabOrientation,
+    action: Option<FileAction>,
+}
+
+impl Default for EditorSettings {
+    fn default() -> Self {
+        Self {
+            tool: EditorTool::Brush,
+            material: 0,
+            erase: false,
+            radius: 2,
+            flood_diagonal: false,
+            corner: None,
+            selection: None,
+            path: "export.vox".into(),
+            import_clear: false,
+            prefab_path: "assets/prefabs/wagon.prefab".into(),
+            prefab_orientation: default(),
+            action: None,
+        }
+    }
+}
+
+impl EditorSettings {
+    fn tile(&self) -> Tile {
+        match self.erase {
+            true => Tile::EMPTY,
+            false => Tile::solid(self.material),
+        }
+    }
+}
+
+fn toggle_editor(
+    keys: Res<ButtonInput<KeyCode>>,
+    settings: Res<Settings>,
+    state: Res<State<GameState>>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if !settings.keys.just_pressed(&keys, Action::ToggleEditor) {
+        return;
+    }
+    match state.get() {
+        GameState::Playing => next_state.set(GameState::Editor),
+        GameState::Editor => next_state.set(GameState::Playing),
+        _ => {}
+    }
+}
+
+fn editor_ui(
+    mut contexts: EguiContexts,
+    mut settings: ResMut<EditorSettings>,
+    atlas: Res<TileAtlas>,
+    mut atlas_texture: Local<Option<egui::TextureId>>,
+) {
+    let texture = *atlas_texture.get_or_insert_with(|| contexts.add_image(atlas.image.clone()));
+    let settings = settings.as_mut();
+
+    egui::Window::new("Editor").show(contexts.ctx_mut(), |ui| {
+        ui.label("Tool");
+        ui.horizontal(|ui| {
+            ui.selectable_value(&mut settings.tool, EditorTool::Brush, "Brush");
+            ui.selectable_value(&mut settings.tool, EditorTool::BoxFill, "Box");
+            ui.selectable_value(&mut settings.tool, EditorTool::FloodFill, "Flood");
+        });
+        ui.horizontal(|ui| {
+            let sphere = EditorTool::Carve(CarveShape::Sphere);
+            let cylinder = EditorTool::Carve(CarveShape::Cylinder);
+            ui.selectable_value(&mut settings.tool, sphere, "Carve sphere");
+            ui.selectable_value(&mut settings.tool, cylinder, "Carve cylinder");
+        });
+        if let EditorTool::Carve(_) = settings.tool {
+            ui.add(egui::Slider::new(&mut settings.radius, 1..=8).text("Radius"));
+        }
+        if settings.tool == EditorTool::FloodFill {
+            ui.checkbox(&mut settings.flood_diagonal, "Connect diagonals");
+        }
+        ui.checkbox(&mut settings.erase, "Erase");
+        ui.selectable_value(&mut settings.tool, EditorTool::Select, "Select");
+
+        ui.separator();
+        ui.label("Material");
+        ui.horizontal_wrapped(|ui| {
+            for index in 0..atlas.layout.textures.len() {
+                let Ok([min, _, max, _]) = atlas.get_uvs(index) else {
+                    continue;
+                };
+                let image = egui::Image::new(SizedTexture::new(texture, [32.0, 32.0])).uv(
+                    egui::Rect::from_min_max(egui::pos2(min.x, min.y), egui::pos2(max.x, max.y)),
+                );
+                let selected = settings.material == index;
+                if ui
+                    .add(egui::ImageButton::new(image).selected(selected))
+                    .clicked()
+                {
+                    settings.material = index;
+                    settings.erase = false;
+                }
+            }
+        });
+
+        ui.separator();
+        ui.label("MagicaVoxel");
+        ui.text_edit_singleline(&mut settings.path);
+        ui.horizontal(|ui| {
+            let export = ui.add_enabled(
+                settings.selection.is_some(),
+                egui::Button::new("Export selection"),
+            );
+            if export.clicked() {
+                settings.action = Some(FileAction::ExportVox);
+            }
+            if ui.button("Import at cursor").clicked() {
+                settings.action = Some(FileAction::ImportVox);
+            }
+        });
+        ui.checkbox(&mut settings.import_clear, "Import clears empty voxels");
+        let export = ui.add_enabled(
+            settings.selection.is_some(),
+            egui::Button::new("Export selected chunks as .glb"),
+        );
+        if export.clicked() {
+            settings.action = Some(FileAction::ExportGltf);
+        }
+
+        ui.separator();
+        ui.label("Prefab");
+        ui.text_edit_singleline(&mut settings.prefab_path);
+        let orientation = &mut settings.prefab_orientation;
+        ui.horizontal(|ui| {
+            if ui.button("Rotate").clicked() {
+                orientation.quarter_turns = (orientation.quarter_turns + 1) % 4;
+            }
+            ui.label(format!("{}°", orientation.quarter_turns as u32 * 90));
+            ui.checkbox(&mut orientation.mirror, "Mirror");
+        });
+        if ui.button("Stamp at cursor").clicked() {
+            settings.action = Some(FileAction::StampPrefab);
+        }
+    });
+
+    if !matches!(settings.tool, EditorTool::BoxFill | EditorTool::Select) {
+        settings.corner = None;
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn apply_tool(
+    mut map: MapCommands,
+    atlas: Res<TileAtlas>,
+    images: Res<Assets<Image>>,
+    mut contexts: EguiContexts,
+    mouse: Res<ButtonInput<MouseButton>>,
+    keys: Res<ButtonInput<KeyCode>>,
+    cursor: Res<TileCursor>,
+    mut settings: ResMut<EditorSettings>,
+    game_settings: Res<Settings>,
+    mut last_painted: Local<Option<UVec3>>,
+) {
+    if game_settings.keys.just_pressed(&keys, Action::Cancel) {
+        settings.corner = None;
+    }
+    if let Some(action) = settings.action.take() {
+        run_file_action(&mut map, &settings, action, cursor.hovered, &atlas, &images);
+    }
+    if mouse.just_pressed(MouseButton::Left) && settings.tool == EditorTool::Brush {
+        map.begin_transaction("Brush");
+    }
+    if mouse.just_released(MouseButton::Left) {
+        map.commit_transaction();
+        *last_painted = None;
+    }
+    // clicks on the editor window are not meant for the map
+    if contexts.ctx_mut().wants_pointer_input() {
+        return;
+    }
+    let Some(tile) = cursor.hovered else {
+        return;
+    };
+    let paint = settings.tile();
+
+    match settings.tool {
+        EditorTool::Brush if mouse.pressed(MouseButton::Left) && *last_painted != Some(tile) => {
+            // fast strokes skip tiles between frames, fill them in
+            let from = last_painted.unwrap_or(tile);
+            tools::set_all(&mut map, region::line(from, tile), paint);
+            *last_painted = Some(tile);
+        }
+        EditorTool::BoxFill if mouse.just_pressed(MouseButton::Left) => {
+            match settings.corner.take() {
+                None => settings.corner = Some(tile),
+                Some(corner) => {
+                    map.begin_transaction("Box fill");
+                    tools::box_fill(&mut map, corner, tile, paint);
+                }
+            }
+        }
+        EditorTool::Select if mouse.just_pressed(MouseButton::Left) => {
+            match settings.corner.take() {
+                None => settings.corner = Some(tile),
+                Some(corner) => settings.selection = Some((corner.min(tile), corner.max(tile))),
+            }
+        }
+        EditorTool::FloodFill if mouse.just_pressed(MouseButton::Left) => {
+            map.begin_transaction("Flood fill");
+            let connected = match settings.flood_diagonal {
+                true => Neighbourhood::All,
+                false => Neighbourhood::Faces,
+            };
+            tools::flood_fill(&mut map, tile, paint, connected);
+        }
+        EditorTool::Carve(shape) if mouse.just_pressed(MouseButton::Left) => {
+            map.begin_transaction(format!("Carve {shape:?}"));
+            tools::carve(&mut map, tile, settings.radius, shape, Tile::EMPTY);
+        }
+        _ => {}
+    }
+}
+
+fn run_file_action(
+    map: &mut MapCommands,
+    settings: &EditorSettings,
+    action: FileAction,
+    hovered: Option<UVec3>,
+    atlas: &TileAtlas,
+    images: &Assets<Image>,
+) {
+    let mut path = settings.path.clone();
+    let result = match action {
+        FileAction::ExportVox => {
+            let Some((min, max)) = settings.selection else {
+                return;
+            };
+            VoxModel::from_region(min, max, |pos| {
+                map.get_tile(pos).ok().map(|tile| tile.get())
+            })
+            .and_then(|mut model| {
+                model.palette = vox::atlas_palette(atlas, images);
+                Ok(std::fs::write(&path, model.to_bytes())?)
+            })
+            .map_err(|err| err.to_string())
+        }
+        FileAction::ImportVox => {
+            let Some(origin) = settings.selection.map(|(min, _)| min).or(hovered) else {
+                return;
+            };
+            std::fs::read(&path)
+                .map_err(VoxError::from)
+                .and_then(|bytes| VoxModel::from_bytes(&bytes))
+                .map_err(|err| err.to_string())
+                .and_then(|model| {
+                    let colors = vox::atlas_palette(atlas, images);
+                    map.begin_transaction(format!("Import {path}"));
+                    let result = model.stamp(map, origin, settings.import_clear, &colors);
+                    map.commit_transaction();
+                    result.map_err(|err| err.to_string())
+                })
+        }
+        FileAction::ExportGltf => {
+            let Some((min, max)) = settings.selection else {
+                return;
+            };
+            path = Path::new(&path).with_extension("glb").display().to_string();
+            let (min, max) = (min / CHUNK_SIZE as u32, max / CHUNK_SIZE as u32);
+            let cords = (min.y..=max.y).flat_map(|y| {
+                (min.x..=max.x).flat_map(move |x| (min.z..=max.z).map(move |z| UVec3::new(x, y, z)))
+            });
+            map.chunks_to_glb(cords, atlas, images)
+                .map_err(|err| err.to_string())
+                .and_then(|glb| std::fs::write(&path, glb).map_err(|err| err.to_string()))
+        }
+        FileAction::StampPrefab => {
+            let Some(pos) = hovered else {
+                return;
+            };
+            path = settings.prefab_path.clone();
+            std::fs::read_to_string(&path)
+                .map_err(PrefabError::from)
+                .and_then(|text| Prefab::parse(&text))
+                .and_then(|prefab| {
+                    let materials = atlas.layout.textures.len();
+                    map.begin_transaction(format!("Stamp {path}"));
+                    let spawned =
+                        map.stamp_prefab(&prefab, pos, settings.prefab_orientation, materials);
+                    map.commit_transaction();
+                    spawned
+                })
+                .map(|spawned| {
+                    if !spawned.is_empty() {
+                        // only tile changes are replayed, see `record_editor_changes`
+                        warn!(
+                            "Spawned {} entities from {path}, they are not part of the save",
+                            spawned.len()
+                        );
+                    }
+                })
+                .map_err(|err| err.to_string())
+        }
+    };
+    match result {
+        Ok(()) => info!("{action:?} {path} done"),
+        Err(err) => error!("{action:?} {path} failed: {err}"),
+    }
+}
+
+fn draw_tool_preview(settings: Res<EditorSettings>, cursor: Res<TileCursor>, mut gizmos: Gizmos) {
+    if let Some((min, max)) = settings.selection {
+        let (min, max) = (tile_to_world(min), tile_to_world(max));
+        gizmos.cuboid(
+            Transform::from_translation((min + max) / 2.0).with_scale(max - min + Vec3::ONE),
+            Color::CYAN,
+        );
+    }
+    let Some(tile) = cursor.hovered else {
+        return;
+    };
+    let color = Color::rgba(1.0, 0.5, 0.1, 0.8);
+
+    match settings.tool {
+        EditorTool::BoxFill | EditorTool::Select => {
+            let corner = settings.corner.unwrap_or(tile);
+            let (min, max) = (
+                tile_to_world(corner.min(tile)),
+                tile_to_world(corner.max(tile)),
+            );
+            gizmos.cuboid(
+                Transform::from_translation((min + max) / 2.0).with_scale(max - min + Vec3::ONE),
+                color,
+            );
+        }
+        EditorTool::Carve(shape) => {
+            let radius = settings.radius as f32 + 0.5;
+            let center = tile_to_world(tile);
+            match shape {
+                CarveShape::Sphere => {
+                    gizmos.sphere(center, Quat::IDENTITY, radius, color);
+                }
+                CarveShape::Cylinder => {
+                    for y in [-radius, radius] {
+                        gizmos.circle(center + Vec3::Y * y, Direction3d::Y, radius, color);
+                    }
+                }
+            }
+        }
+        EditorTool::Brush | EditorTool::FloodFill => {}
+    }
+}
@@ -0,0 +1,67 @@
+use std::collections::VecDeque;
+
+use bevy::{prelude::*, utils::HashSet};
+
+use crate::dwarf_map::{
+    chunk::{MapCommands, Tile},
+    region::{self, Neighbourhood},
+};
+
+/// Flood fills stop after this many tiles, so a click into a huge cave doesn't hang the game.
+pub const MAX_FLOOD: usize = 16 * 16 * 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Reflect)]
+pub enum CarveShape {
+    Sphere,
+    /// Upright, as high as it is wide.
+    Cylinder,
+}
+
+/// Fills the box between two corners, corners on different layers give a 3D box.
+pub fn box_fill(map: &mut MapCommands, a: UVec3, b: UVec3, tile: Tile) {
+    set_all(map, region::aabb(a, b), tile);
+}
+
+/// Replaces all tiles connected to `start` that equal it.
+pub fn flood_fill(map: &mut MapCommands, start: UVec3, tile: Tile, connected: Neighbourhood) {
+    let Ok(target) = map.get_tile(start).map(|t| t.get()) else {
+        return;
+    };
+    if target == tile {
+        return;
+    }
+
+    let mut queue = VecDeque::from([start]);
+    let mut seen = HashSet::from([start]);
+    while let Some(pos) = queue.pop_front() {
+        if let Ok(mut commands) = map.get_tile(pos) {
+            commands.set(tile);
+        }
+
+        for next in region::neighbours(pos, connected) {
+            if seen.len() >= MAX_FLOOD || !seen.insert(next) {
+                continue;
+            }
+            if map.get_tile(next).map(|t| t.get()) == Ok(target) {
+                queue.push_back(next);
+            }
+        }
+    }
+}
+
+/// Sets every tile within `radius` of `center`.
+pub fn carve(map: &mut MapCommands, center: UVec3, radius: u32, shape: CarveShape, tile: Tile) {
+    match shape {
+        CarveShape::Sphere => set_all(map, region::sphere(center, radius), tile),
+        CarveShape::Cylinder => set_all(map, region::cylinder(center, radius), tile),
+    }
+}
+
+/// Tiles outside of the loaded chunks are skipped.
+pub fn set_all(map: &mut MapCommands, positions: impl IntoIterator<Item = UVec3>, tile: Tile) {
+    for pos in positions {
+        if let Ok(mut commands) = map.get_tile(pos) {
+            commands.set(tile);
+        }
+    }
+}
@@ -0,0 +1,214 @@
+use bevy::{
+    prelude::*,
+    render::mesh::VertexAttributeValues,
+    utils::{HashMap, HashSet},
+};
+
+use crate::{
+    dwarf_map::{tile_atlas::TileAtlas, tile_to_world, TilePos},
+    prelude::*,
+};
+
+pub mod stockpile;
+
+/// Loose objects lying on the map, like boulders, logs and bars.
+pub struct ItemPlugin;
+
+impl Plugin for ItemPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ItemIndex>()
+            .register_type::<Item>()
+            .register_type::<Reserved>()
+            .add_plugins(stockpile::StockpilePlugin)
+            .add_systems(OnExit(GameState::Loading), init_item_assets)
+            .add_systems(
+                Update,
+                (
+                    index_items,
+                    refresh_item_meshes,
+                    attach_item_visuals,
+                    move_items,
+                )
+                    .run_if(in_world),
+            );
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Reflect)]
+pub enum ItemKind {
+    Boulder,
+    Ore,
+    Log,
+    Bar,
+}
+
+impl ItemKind {
+    pub const ALL: [ItemKind; 4] = [
+        ItemKind::Boulder,
+        ItemKind::Ore,
+        ItemKind::Log,
+        ItemKind::Bar,
+    ];
+}
+
+#[derive(Debug, Component, Clone, Copy, Reflect)]
+#[reflect(Component)]
+pub struct Item {
+    pub kind: ItemKind,
+    /// Material of the item, uses the same index as [`Tile::index`](crate::dwarf_map::chunk::Tile::index).
+    pub material: usize,
+}
+
+/// The item is claimed by another entity, like a blueprint, and must not be used by anything else.
+#[derive(Debug, Component, Clone, Copy, Reflect)]
+#[reflect(Component)]
+pub struct Reserved(pub Entity);
+
+#[derive(Bundle)]
+pub struct ItemBundle {
+    pub item: Item,
+    pub pos: TilePos,
+    pub name: Name,
+}
+
+impl ItemBundle {
+    pub fn new(kind: ItemKind, material: usize, pos: UVec3) -> Self {
+        Self {
+            item: Item { kind, material },
+            pos: TilePos(pos),
+            name: Name::new(format!("{kind:?}")),
+        }
+    }
+}
+
+/// Spatial index of all items, grouped by the chunk they are in.
+#[derive(Resource, Default)]
+pub struct ItemIndex {
+    chunks: HashMap<UVec3, HashSet<Entity>>,
+    positions: HashMap<Entity, TilePos>,
+}
+
+impl ItemIndex {
+    pub fn items_in_chunk(&self, chunk: UVec3) -> impl Iterator<Item = Entity> + '_ {
+        self.chunks.get(&chunk).into_iter().flatten().copied()
+    }
+
+    pub fn items_on_tile(&self, tile: UVec3) -> impl Iterator<Item = Entity> + '_ {
+        let pos = TilePos(tile);
+        self.items_in_chunk(pos.chunk())
+            .filter(move |e| self.positions.get(e) == Some(&pos))
+    }
+
+    fn insert(&mut self, entity: Entity, pos: TilePos) {
+        self.remove(entity);
+        self.chunks.entry(pos.chunk()).or_default().insert(entity);
+        self.positions.insert(entity, pos);
+    }
+
+    fn remove(&mut self, entity: Entity) {
+        if let Some(old) = self.positions.remove(&entity) {
+            if let Some(items) = self.chunks.get_mut(&old.chunk()) {
+                items.remove(&entity);
+                if items.is_empty() {
+                    self.chunks.remove(&old.chunk());
+                }
+            }
+        }
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn index_items(
+    mut index: ResMut<ItemIndex>,
+    moved: Query<(Entity, &TilePos), (With<Item>, Changed<TilePos>)>,
+    mut removed: RemovedComponents<Item>,
+) {
+    for entity in removed.read() {
+        index.remove(entity);
+    }
+    for (entity, pos) in moved.iter() {
+        index.insert(entity, *pos);
+    }
+}
+
+/// One mesh per tile material, with the uvs pointing into the atlas.
+#[derive(Resource)]
+struct ItemAssets {
+    meshes: Vec<Handle<Mesh>>,
+}
+
+const ITEM_SIZE: f32 = 0.4;
+
+fn init_item_assets(
+    mut commands: Commands,
+    atlas: Res<TileAtlas>,
+    mut mesh_assets: ResMut<Assets<Mesh>>,
+) {
+    let meshes = (0..atlas.layout.textures.len())
+        .map(|index| mesh_assets.add(item_mesh(&atlas, index)))
+        .collect();
+
+    commands.insert_resource(ItemAssets { meshes });
+}
+
+fn item_mesh(atlas: &TileAtlas, index: usize) -> Mesh {
+    // every index of the layout is valid
+    let [min, _, max, _] = atlas.get_uvs(index).unwrap_or_default();
+    let mut mesh = Mesh::from(Cuboid::new(ITEM_SIZE, ITEM_SIZE, ITEM_SIZE));
+    if let Some(VertexAttributeValues::Float32x2(uvs)) = mesh.attribute_mut(Mesh::ATTRIBUTE_UV_0) {
+        for uv in uvs.iter_mut() {
+            *uv = (Vec2::from(*uv) * (max - min) + min).into();
+        }
+    }
+    mesh
+}
+
+/// Points the item meshes at the new layout after the [`TileAtlas`] was reloaded,
+/// the handles stay the same so spawned items follow along.
+fn refresh_item_meshes(
+    atlas: Res<TileAtlas>,
+    assets: Res<ItemAssets>,
+    mut mesh_assets: ResMut<Assets<Mesh>>,
+) {
+    if !atlas.is_changed() || atlas.is_added() {
+        return;
+    }
+    for (index, handle) in assets.meshes.iter().enumerate() {
+        mesh_assets.insert(handle, item_mesh(&atlas, index));
+    }
+}
+
+fn item_transform(pos: &TilePos) -> Transform {
+    // resting on the floor of the tile
+    Transform::from_translation(tile_to_world(pos.0) - Vec3::Y * (0.5 - ITEM_SIZE / 2.0))
+}
+
+fn attach_item_visuals(
+    mut commands: Commands,
+    assets: Res<ItemAssets>,
+    atlas: Res<TileAtlas>,
+    items: Query<(Entity, &Item, &TilePos), Added<Item>>,
+) {
+    for (entity, item, pos) in items.iter() {
+        let mesh = assets
+            .meshes
+            .get(item.material)
+            .or(assets.meshes.first())
+            .cloned()
+            .unwrap_or_default();
+
+        commands.entity(entity).insert(PbrBundle {
+            mesh,
+            material: atlas.material.clone(),
+            transform: item_transform(pos),
+            ..default()
+        });
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn move_items(mut items: Query<(&TilePos, &mut Transform), (With<Item>, Changed<TilePos>)>) {
+    for (pos, mut transform) in items.iter_mut() {
+        *transform = item_transform(pos);
+    }
+}
@@ -0,0 +1,135 @@
+use bevy::prelude::*;
+
+use super::{Item, ItemIndex};
+use crate::{
+    dwarf_map::{cursor::TileCursor, tile_to_world, CurrentMapLayer},
+    prelude::*,
+    settings::{Action, Settings},
+    simulation::{
+        replay::{PlayerCommand, PlayerCommands, TickCommands},
+        Simulation, SimulationSet,
+    },
+};
+
+/// Rectangular zones on a single layer where items get stored.
+pub struct StockpilePlugin;
+
+impl Plugin for StockpilePlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<Stockpile>()
+            .add_systems(
+                Update,
+                (designate_stockpile, draw_stockpiles).run_if(in_state(GameState::Playing)),
+            )
+            .add_systems(
+                Simulation,
+                apply_stockpile_commands
+                    .in_set(SimulationSet::Update)
+                    .run_if(in_simulation),
+            );
+    }
+}
+
+#[derive(Debug, Component, Reflect, Clone, Copy)]
+#[reflect(Component)]
+pub struct Stockpile {
+    /// Smallest corner, in world tile coordinates.
+    pub min: UVec3,
+    /// Largest corner, has the same layer as `min`.
+    pub max: UVec3,
+}
+
+impl Stockpile {
+    /// Creates a stockpile spanning both corners, on the layer of `a`.
+    pub fn new(a: UVec3, b: UVec3) -> Self {
+        let min = a.min(b);
+        let max = a.max(b);
+        Self {
+            min: UVec3::new(min.x, a.y, min.z),
+            max: UVec3::new(max.x, a.y, max.z),
+        }
+    }
+
+    pub fn tiles(&self) -> impl Iterator<Item = UVec3> + '_ {
+        (self.min.x..=self.max.x)
+            .flat_map(move |x| (self.min.z..=self.max.z).map(move |z| UVec3::new(x, self.min.y, z)))
+    }
+
+    /// All items currently lying inside the stockpile.
+    pub fn items<'a>(&'a self, index: &'a ItemIndex) -> impl Iterator<Item = Entity> + 'a {
+        self.tiles().flat_map(|tile| index.items_on_tile(tile))
+    }
+}
+
+/// Press `I` on two tiles to place a stockpile between them.
+fn designate_stockpile(
+    mut player_commands: ResMut<PlayerCommands>,
+    keys: Res<ButtonInput<KeyCode>>,
+    settings: Res<Settings>,
+    cursor: Res<TileCursor>,
+    mut first_corner: Local<Option<UVec3>>,
+) {
+    if settings.keys.just_pressed(&keys, Action::Cancel) {
+        *first_corner = None;
+    }
+    if !settings
+        .keys
+        .just_pressed(&keys, Action::DesignateStockpile)
+    {
+        return;
+    }
+    let Some(tile) = cursor.hovered else {
+        return;
+    };
+
+    match first_corner.take() {
+        None => *first_corner = Some(tile),
+        Some(first) => {
+            player_commands.send(PlayerCommand::DesignateStockpile { a: first, b: tile })
+        }
+    }
+}
+
+fn apply_stockpile_commands(mut commands: Commands, tick_commands: Res<TickCommands>) {
+    for command in tick_commands.iter() {
+        if let PlayerCommand::DesignateStockpile { a, b } = *command {
+            commands.spawn((Stockpile::new(a, b), Name::new("Stockpile")));
+        }
+    }
+}
+
+fn draw_stockpiles(
+    layer: Res<CurrentMapLayer>,
+    stockpiles: Query<&Stockpile>,
+    items: Query<&Item>,
+    index: Res<ItemIndex>,
+    mut gizmos: Gizmos,
+) {
+    for stockpile in stockpiles.iter() {
+        if stockpile.min.y as usize > layer.0 {
+            continue;
+        }
+
+        let min = tile_to_world(stockpile.min);
+        let max = tile_to_world(stockpile.max);
+        let size = (max - min).xz() + Vec2::ONE;
+        // on the floor of the layer
+        let center = (min + max) / 2.0 - Vec3::Y * 0.49;
+
+        let used = stockpile
+            .items(&index)
+            .filter(|e| items.contains(*e))
+            .count();
+        let color = match used {
+            0 => Color::rgba(0.9, 0.8, 0.2, 0.8),
+            _ => Color::rgba(0.3, 0.9, 0.3, 0.8),
+        };
+
+        gizmos.rect(
+            center,
+            Quat::from_rotation_x(std::f32::consts::FRAC_PI_2),
+            size,
+            color,
+        );
+    }
+}
@@ -1,4 +1,4 @@
-use bevy::{prelude::*, window::PresentMode};
+use bevy::prelude::*;
 use bevy_inspector_egui::quick::{ResourceInspectorPlugin, WorldInspectorPlugin};
 use prelude::LoadingState;
 use smooth_bevy_cameras::{
@@ -7,7 +7,15 @@ use smooth_bevy_cameras::{
 };
 
 mod assets;
+mod buildings;
+mod camera;
 mod dwarf_map;
+mod dwarves;
+mod editor;
+mod items;
+mod menu;
+mod settings;
+mod simulation;
 mod states;
 
 pub mod prelude {
@@ -17,31 +25,39 @@ pub mod prelude {
 fn main() {
     let mut app = App::new();
 
-    app.insert_resource(Msaa::Off)
-        .insert_state(states::GameState::Loading)
+    app.insert_state(states::GameState::Loading)
         .insert_state(LoadingState::LoadingAssets)
         .add_plugins(DefaultPlugins.set(WindowPlugin {
             primary_window: Some(Window {
                 title: "Bevy Dwarf Fortress like 3D".into(),
                 name: Some("bevy.app".into()),
-                present_mode: PresentMode::Immediate,
                 ..default()
             }),
             ..default()
         }))
+        .add_plugins(settings::SettingsPlugin)
         .add_plugins(LookTransformPlugin)
         .add_plugins(FpsCameraPlugin::default())
         .add_plugins((dwarf_map::DwarfMapPlugin, assets::DwarfAssetPlugin))
+        .add_plugins(camera::DwarfCameraPlugin)
+        .add_plugins((
+            simulation::SimulationPlugin::from_args(),
+            items::ItemPlugin,
+            buildings::BuildingPlugin,
+            dwarves::DwarfPlugin,
+        ))
+        .add_plugins(editor::EditorPlugin)
+        .add_plugins(menu::MenuPlugin)
         .add_systems(Startup, setup);
-    //#[cfg(debug_assertions)]
-    {
+    if cfg!(debug_assertions) || app.world.resource::<settings::Settings>().diagnostics {
         use bevy::diagnostic::LogDiagnosticsPlugin;
         app.add_plugins(LogDiagnosticsPlugin::default());
         use bevy::diagnostic::FrameTimeDiagnosticsPlugin;
         app.add_plugins(FrameTimeDiagnosticsPlugin);
         app.add_plugins(WorldInspectorPlugin::new())
-            .register_type::<dwarf_map::chunk::ChunkCord>()
-    };
+            .add_plugins(ResourceInspectorPlugin::<simulation::SimulationClock>::default())
+            .register_type::<dwarf_map::chunk::ChunkCord>();
+    }
     app.run();
 }
 
@@ -49,6 +65,7 @@ fn setup(mut commands: Commands) {
     // camera
     commands
         .spawn(Camera3dBundle::default())
+        .insert(camera::MainCamera)
         .insert(FpsCameraBundle::new(
             FpsCameraController::default(),
             Vec3::new(-2.0, 5.0, 5.0),
@@ -0,0 +1,368 @@
+use std::{fs, path::PathBuf};
+
+use bevy::{app::AppExit, prelude::*};
+use bevy_inspector_egui::{bevy_egui::EguiContexts, egui};
+
+use crate::{
+    dwarf_map::{
+        chunk::lod::LodSettings,
+        generation::{WorldGeneration, WorldSettings},
+        tile_atlas::TileRenderMode,
+    },
+    prelude::*,
+    settings::{self, Action, Keybindings, Settings},
+    simulation::{
+        calendar::Date,
+        replay::{self, InputLog, Replay},
+        SkipMainMenu,
+    },
+};
+
+/// Main menu and the screens behind it, everything before a world is generated.
+/// `F10` saves the game and goes back to the main menu.
+pub struct MenuPlugin;
+
+impl Plugin for MenuPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<NewGameSettings>()
+            .init_resource::<SaveList>()
+            .add_systems(OnEnter(GameState::MainMenu), skip_main_menu)
+            .add_systems(OnEnter(GameState::LoadGame), list_saves)
+            .add_systems(
+                Update,
+                (
+                    main_menu_ui.run_if(in_state(GameState::MainMenu)),
+                    new_game_ui.run_if(in_state(GameState::NewGame)),
+                    load_game_ui.run_if(in_state(GameState::LoadGame)),
+                    settings_ui.run_if(in_state(GameState::Settings)),
+                    quit_to_menu.run_if(in_world),
+                ),
+            );
+    }
+}
+
+/// Inputs of the new game screen, kept for the next visit.
+#[derive(Debug, Resource)]
+struct NewGameSettings {
+    seed: String,
+    world: WorldSettings,
+}
+
+impl Default for NewGameSettings {
+    fn default() -> Self {
+        Self {
+            seed: rand::random::<u64>().to_string(),
+            world: WorldSettings::default(),
+        }
+    }
+}
+
+/// Saves found when the load game screen was opened, with the reason if one can't be read.
+#[derive(Debug, Resource, Default)]
+struct SaveList(Vec<(PathBuf, Result<InputLog, String>)>);
+
+/// Fixed window in the middle of the screen.
+fn menu_window(title: &str) -> egui::Window<'_> {
+    egui::Window::new(title)
+        .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+        .collapsible(false)
+        .resizable(false)
+}
+
+/// Generates the world of `replay` and starts playing it.
+fn start_game(
+    commands: &mut Commands,
+    next_state: &mut NextState<GameState>,
+    replay: Replay,
+    catch_up: bool,
+) {
+    commands.insert_resource(WorldGeneration::new(replay.log.world, catch_up));
+    commands.insert_resource(replay);
+    next_state.set(GameState::Generating);
+}
+
+/// A world given on the command line starts right away, a replay is played back in game.
+fn skip_main_menu(
+    mut commands: Commands,
+    skip: Option<Res<SkipMainMenu>>,
+    replay: Res<Replay>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if skip.is_none() {
+        return;
+    }
+    commands.remove_resource::<SkipMainMenu>();
+    commands.insert_resource(WorldGeneration::new(replay.log.world, false));
+    next_state.set(GameState::Generating);
+}
+
+fn main_menu_ui(
+    mut contexts: EguiContexts,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut exit: EventWriter<AppExit>,
+) {
+    menu_window("Bevy Dwarf Fortress").show(contexts.ctx_mut(), |ui| {
+        ui.vertical_centered_justified(|ui| {
+            if ui.button("New game").clicked() {
+                next_state.set(GameState::NewGame);
+            }
+            if ui.button("Load game").clicked() {
+                next_state.set(GameState::LoadGame);
+            }
+            if ui.button("Settings").clicked() {
+                next_state.set(GameState::Settings);
+            }
+            if ui.button("Quit").clicked() {
+                exit.send(AppExit);
+            }
+        });
+    });
+}
+
+fn new_game_ui(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    mut settings: ResMut<NewGameSettings>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    let settings = settings.as_mut();
+    let max = WorldSettings::MAX_SIZE;
+
+    menu_window("New game").show(contexts.ctx_mut(), |ui| {
+        ui.horizontal(|ui| {
+            ui.label("Seed");
+            ui.text_edit_singleline(&mut settings.seed);
+            if ui.button("Random").clicked() {
+                settings.seed = rand::random::<u64>().to_string();
+            }
+        });
+        let seed = settings.seed.trim().parse::<u64>();
+        if seed.is_err() {
+            ui.colored_label(egui::Color32::LIGHT_RED, "The seed has to be a number");
+        }
+
+        ui.separator();
+        ui.label("World size in chunks");
+        let world = &mut settings.world;
+        ui.add(egui::Slider::new(&mut world.size.x, 1..=max.x).text("Width"));
+        ui.add(egui::Slider::new(&mut world.size.z, 1..=max.z).text("Length"));
+        ui.add(egui::Slider::new(&mut world.size.y, 1..=max.y).text("Height"));
+        world.embark = world.embark.min(world.size.xz() - UVec2::ONE);
+
+        ui.separator();
+        ui.label("Embark location");
+        egui::Grid::new("embark").show(ui, |ui| {
+            for z in 0..world.size.z {
+                for x in 0..world.size.x {
+                    ui.selectable_value(&mut world.embark, UVec2::new(x, z), format!("{x},{z}"));
+                }
+                ui.end_row();
+            }
+        });
+
+        ui.separator();
+        ui.horizontal(|ui| {
+            if ui.button("Back").clicked() {
+                next_state.set(GameState::MainMenu);
+            }
+            let embark = ui.add_enabled(seed.is_ok(), egui::Button::new("Embark"));
+            if let (true, Ok(seed)) = (embark.clicked(), seed) {
+                world.seed = seed;
+                let replay = Replay::record(*world);
+                start_game(&mut commands, &mut next_state, replay, false);
+            }
+        });
+    });
+}
+
+fn list_saves(mut saves: ResMut<SaveList>) {
+    let mut paths: Vec<_> = fs::read_dir(replay::SAVE_DIR)
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "replay"))
+        .collect();
+    paths.sort();
+
+    saves.0 = paths
+        .into_iter()
+        .map(|path| {
+            let log = replay::read_log(&path);
+            (path, log)
+        })
+        .collect();
+}
+
+fn load_game_ui(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    saves: Res<SaveList>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    menu_window("Load game").show(contexts.ctx_mut(), |ui| {
+        if saves.0.is_empty() {
+            ui.label(format!("No saves in {}", replay::SAVE_DIR));
+        }
+        egui::Grid::new("saves").striped(true).show(ui, |ui| {
+            for (path, log) in &saves.0 {
+                let name = path.file_stem().unwrap_or_default().to_string_lossy();
+                ui.label(name.to_string());
+                match log {
+                    Ok(log) => {
+                        let world = &log.world;
+                        ui.label(format!(
+                            "seed {}, {} chunks, {}",
+                            world.seed,
+                            world.size,
+                            Date::from_tick(log.last_tick())
+                        ));
+                        if ui.button("Load").clicked() {
+                            let replay = Replay::play(log.clone());
+                            start_game(&mut commands, &mut next_state, replay, true);
+                        }
+                    }
+                    Err(err) => {
+                        ui.colored_label(egui::Color32::LIGHT_RED, err);
+                    }
+                }
+                ui.end_row();
+            }
+        });
+
+        ui.separator();
+        if ui.button("Back").clicked() {
+            next_state.set(GameState::MainMenu);
+        }
+    });
+}
+
+fn settings_ui(
+    mut contexts: EguiContexts,
+    keys: Res<ButtonInput<KeyCode>>,
+    mut settings: ResMut<Settings>,
+    mut mode: ResMut<TileRenderMode>,
+    mut lod: ResMut<LodSettings>,
+    mut next_state: ResMut<NextState<GameState>>,
+    // the action waiting for a key to be bound to
+    mut rebinding: Local<Option<Action>>,
+) {
+    if let Some(action) = *rebinding {
+        if keys.just_pressed(KeyCode::Escape) {
+            *rebinding = None;
+        } else if let Some(key) = settings::keys::BINDABLE_KEYS
+            .iter()
+            .find(|key| keys.just_pressed(**key))
+        {
+            settings.keys.set(action, *key);
+            *rebinding = None;
+        }
+    }
+    // egui hands out `&mut` every frame, only a widget that was changed marks its resource
+    let (mut settings_changed, mut mode_changed, mut lod_changed) = (false, false, false);
+    let settings_ref = settings.bypass_change_detection();
+    let mode_ref = mode.bypass_change_detection();
+    let lod_ref = lod.bypass_change_detection();
+
+    menu_window("Settings").show(contexts.ctx_mut(), |ui| {
+        let settings = settings_ref;
+        let lod = lod_ref;
+        ui.label("Graphics");
+        settings_changed |= ui.checkbox(&mut settings.vsync, "VSync").changed();
+        ui.horizontal(|ui| {
+            ui.label("MSAA");
+            for samples in settings::MSAA_SAMPLES {
+                let label = match samples {
+                    1 => "Off".to_string(),
+                    _ => format!("{samples}x"),
+                };
+                settings_changed |= ui
+                    .selectable_value(&mut settings.msaa, samples, label)
+                    .changed();
+            }
+        });
+        settings_changed |= ui
+            .add(
+                egui::Slider::new(&mut settings.render_distance, 1..=32)
+                    .text("Render distance in chunks"),
+            )
+            .changed();
+        ui.horizontal(|ui| {
+            ui.label("Tile rendering");
+            mode_changed |= ui
+                .selectable_value(mode_ref, TileRenderMode::Atlas, "Atlas")
+                .changed();
+            mode_changed |= ui
+                .selectable_value(mode_ref, TileRenderMode::Array, "Array")
+                .changed();
+        });
+        lod_changed |= ui.checkbox(&mut lod.enabled, "Level of detail").changed();
+        lod_changed |= ui
+            .add_enabled(
+                lod.enabled,
+                egui::Slider::new(&mut lod.half_distance, 16.0..=256.0).text("Half detail from"),
+            )
+            .changed();
+        lod_changed |= ui
+            .add_enabled(
+                lod.enabled,
+                egui::Slider::new(&mut lod.quarter_distance, 16.0..=512.0)
+                    .text("Quarter detail from"),
+            )
+            .changed();
+        settings_changed |= ui
+            .checkbox(&mut settings.diagnostics, "Diagnostics (after a restart)")
+            .changed();
+
+        ui.separator();
+        ui.label("Controls");
+        settings_changed |= ui
+            .add(
+                egui::Slider::new(&mut settings.camera_sensitivity, 0.1..=5.0)
+                    .text("Camera sensitivity"),
+            )
+            .changed();
+        egui::Grid::new("keys").striped(true).show(ui, |ui| {
+            for action in Action::ALL {
+                ui.label(format!("{action:?}"));
+                let text = match *rebinding == Some(action) {
+                    true => "Press a key".to_string(),
+                    false => format!("{:?}", settings.keys.key(action)),
+                };
+                if ui.button(text).clicked() {
+                    *rebinding = Some(action);
+                }
+                ui.end_row();
+            }
+        });
+        if ui.button("Reset keys").clicked() {
+            settings.keys = Keybindings::default();
+            settings_changed = true;
+        }
+
+        ui.separator();
+        if ui.button("Back").clicked() {
+            *rebinding = None;
+            next_state.set(GameState::MainMenu);
+        }
+    });
+
+    if settings_changed {
+        settings.set_changed();
+    }
+    if mode_changed {
+        mode.set_changed();
+    }
+    if lod_changed {
+        lod.set_changed();
+    }
+}
+
+fn quit_to_menu(
+    keys: Res<ButtonInput<KeyCode>>,
+    settings: Res<Settings>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if settings.keys.just_pressed(&keys, Action::QuitToMenu) {
+        next_state.set(GameState::MainMenu);
+    }
+}
@@ -0,0 +1,261 @@
+use bevy::{prelude::*, utils::HashMap};
+
+/// Something a hotkey does. `Ctrl+Z`/`Ctrl+Y` for undo and redo stay fixed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Action {
+    ToggleEditor,
+    ToggleCamera,
+    QuitToMenu,
+    Pause,
+    Step,
+    SpeedNormal,
+    SpeedFast,
+    SpeedFastest,
+    CycleBuilding,
+    DesignateStockpile,
+    /// Drops a half placed building, stockpile or editor selection.
+    Cancel,
+    /// Panning of the top down and orbit cameras, the fps camera has its own keys.
+    PanUp,
+    PanDown,
+    PanLeft,
+    PanRight,
+    RotateLeft,
+    RotateRight,
+}
+
+impl Action {
+    pub const ALL: [Action; 17] = [
+        Action::ToggleEditor,
+        Action::ToggleCamera,
+        Action::QuitToMenu,
+        Action::Pause,
+        Action::Step,
+        Action::SpeedNormal,
+        Action::SpeedFast,
+        Action::SpeedFastest,
+        Action::CycleBuilding,
+        Action::DesignateStockpile,
+        Action::Cancel,
+        Action::PanUp,
+        Action::PanDown,
+        Action::PanLeft,
+        Action::PanRight,
+        Action::RotateLeft,
+        Action::RotateRight,
+    ];
+
+    pub fn default_key(&self) -> KeyCode {
+        match self {
+            Action::ToggleEditor => KeyCode::F2,
+            Action::ToggleCamera => KeyCode::Tab,
+            Action::QuitToMenu => KeyCode::F10,
+            // the fps camera flies up with `Space`
+            Action::Pause => KeyCode::KeyP,
+            Action::Step => KeyCode::Period,
+            Action::SpeedNormal => KeyCode::Digit1,
+            Action::SpeedFast => KeyCode::Digit2,
+            Action::SpeedFastest => KeyCode::Digit3,
+            Action::CycleBuilding => KeyCode::KeyB,
+            Action::DesignateStockpile => KeyCode::KeyI,
+            Action::Cancel => KeyCode::Escape,
+            Action::PanUp => KeyCode::KeyW,
+            Action::PanDown => KeyCode::KeyS,
+            Action::PanLeft => KeyCode::KeyA,
+            Action::PanRight => KeyCode::KeyD,
+            Action::RotateLeft => KeyCode::KeyQ,
+            Action::RotateRight => KeyCode::KeyE,
+        }
+    }
+}
+
+/// Keys that can be bound, by the name they have in the settings file.
+pub const BINDABLE_KEYS: &[KeyCode] = &[
+    KeyCode::KeyA,
+    KeyCode::KeyB,
+    KeyCode::KeyC,
+    KeyCode::KeyD,
+    KeyCode::KeyE,
+    KeyCode::KeyF,
+    KeyCode::KeyG,
+    KeyCode::KeyH,
+    KeyCode::KeyI,
+    KeyCode::KeyJ,
+    KeyCode::KeyK,
+    KeyCode::KeyL,
+    KeyCode::KeyM,
+    KeyCode::KeyN,
+    KeyCode::KeyO,
+    KeyCode::KeyP,
+    KeyCode::KeyQ,
+    KeyCode::KeyR,
+    KeyCode::KeyS,
+    KeyCode::KeyT,
+    KeyCode::KeyU,
+    KeyCode::KeyV,
+    KeyCode::KeyW,
+    KeyCode::KeyX,
+    KeyCode::KeyY,
+    KeyCode::KeyZ,
+    KeyCode::Digit0,
+    KeyCode::Digit1,
+    KeyCode::Digit2,
+    KeyCode::Digit3,
+    KeyCode::Digit4,
+    KeyCode::Digit5,
+    KeyCode::Digit6,
+    KeyCode::Digit7,
+    KeyCode::Digit8,
+    KeyCode::Digit9,
+    KeyCode::F1,
+    KeyCode::F2,
+    KeyCode::F3,
+    KeyCode::F4,
+    KeyCode::F5,
+    KeyCode::F6,
+    KeyCode::F7,
+    KeyCode::F8,
+    KeyCode::F9,
+    KeyCode::F10,
+    KeyCode::F11,
+    KeyCode::F12,
+    KeyCode::Escape,
+    KeyCode::Space,
+    KeyCode::Tab,
+    KeyCode::Enter,
+    KeyCode::Backspace,
+    KeyCode::Period,
+    KeyCode::Comma,
+    KeyCode::Minus,
+    KeyCode::Equal,
+    KeyCode::Semicolon,
+    KeyCode::Quote,
+    KeyCode::Slash,
+    KeyCode::Backslash,
+    KeyCode::Backquote,
+    KeyCode::BracketLeft,
+    KeyCode::BracketRight,
+    KeyCode::ArrowUp,
+    KeyCode::ArrowDown,
+    KeyCode::ArrowLeft,
+    KeyCode::ArrowRight,
+    KeyCode::Home,
+    KeyCode::End,
+    KeyCode::PageUp,
+    KeyCode::PageDown,
+];
+
+pub fn parse_key(name: &str) -> Option<KeyCode> {
+    BINDABLE_KEYS
+        .iter()
+        .copied()
+        .find(|key| format!("{key:?}") == name)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Keybindings(HashMap<Action, KeyCode>);
+
+impl Default for Keybindings {
+    fn default() -> Self {
+        Self(
+            Action::ALL
+                .into_iter()
+                .map(|action| (action, action.default_key()))
+                .collect(),
+        )
+    }
+}
+
+impl Keybindings {
+    pub fn key(&self, action: Action) -> KeyCode {
+        self.0
+            .get(&action)
+            .copied()
+            .unwrap_or_else(|| action.default_key())
+    }
+
+    /// Binds `key` to `action`, an action that had `key` before gets the old key of `action`,
+    /// so no key ever triggers two actions.
+    pub fn set(&mut self, action: Action, key: KeyCode) {
+        let old = self.key(action);
+        if let Some(other) = Action::ALL
+            .into_iter()
+            .find(|other| *other != action && self.key(*other) == key)
+        {
+            self.0.insert(other, old);
+        }
+        self.0.insert(action, key);
+    }
+
+    pub fn pressed(&self, keys: &ButtonInput<KeyCode>, action: Action) -> bool {
+        keys.pressed(self.key(action))
+    }
+
+    pub fn just_pressed(&self, keys: &ButtonInput<KeyCode>, action: Action) -> bool {
+        keys.just_pressed(self.key(action))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::{parse_settings, write_settings, Settings};
+
+    #[test]
+    fn bindable_keys_parse_by_name() {
+        for key in BINDABLE_KEYS {
+            assert_eq!(parse_key(&format!("{key:?}")), Some(*key));
+        }
+        assert_eq!(parse_key("ShiftLeft"), None);
+        assert_eq!(parse_key(""), None);
+    }
+
+    #[test]
+    fn default_keys_are_bindable_and_distinct() {
+        for (i, action) in Action::ALL.iter().enumerate() {
+            let key = action.default_key();
+            assert!(BINDABLE_KEYS.contains(&key), "{action:?}");
+            for other in &Action::ALL[i + 1..] {
+                assert_ne!(key, other.default_key(), "{action:?} and {other:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn binding_a_taken_key_swaps_the_keys() {
+        let mut keys = Keybindings::default();
+        let pause = Action::Pause.default_key();
+        let step = Action::Step.default_key();
+        keys.set(Action::Pause, step);
+        assert_eq!(keys.key(Action::Pause), step);
+        assert_eq!(keys.key(Action::Step), pause);
+
+        keys.set(Action::Pause, KeyCode::F12);
+        assert_eq!(keys.key(Action::Pause), KeyCode::F12);
+        assert_eq!(keys.key(Action::Step), pause);
+    }
+
+    #[test]
+    fn keybindings_survive_a_round_trip() {
+        let mut settings = Settings::default();
+        settings.keys.set(Action::Pause, KeyCode::Space);
+        settings.keys.set(Action::Cancel, KeyCode::Backspace);
+        settings.keys.set(Action::RotateLeft, KeyCode::F12);
+
+        let read = parse_settings(&write_settings(&settings)).unwrap();
+        assert_eq!(read, settings);
+        assert_eq!(read.keys.key(Action::Pause), KeyCode::Space);
+        assert_eq!(read.keys.key(Action::Step), Action::Step.default_key());
+    }
+
+    #[test]
+    fn unknown_actions_and_keys_are_rejected() {
+        assert!(parse_settings("key Pause P").is_err());
+        assert!(parse_settings("key Jump KeyP").is_err());
+        assert!(parse_settings("key Pause").is_err());
+        assert_eq!(
+            parse_settings("key Pause KeyO").map(|s| s.keys.key(Action::Pause)),
+            Ok(KeyCode::KeyO)
+        );
+    }
+}
@@ -0,0 +1,226 @@
+//! Player settings, read from `settings.cfg` in the user config directory on startup
+//! and written back on exit.
+//!
+//! The file is plain text with one entry per line: `vsync <true|false>`, `msaa <samples>`,
+//! `render_distance <chunks>`, `camera_sensitivity <factor>`, `diagnostics <true|false>`
+//! and `key <action> <key>`, with the names of [`Action`] and [`KeyCode`].
+
+use std::{
+    fmt::Write as _,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use bevy::{
+    app::AppExit,
+    prelude::*,
+    window::{PresentMode, PrimaryWindow},
+};
+use smooth_bevy_cameras::controllers::fps::FpsCameraController;
+
+use crate::camera::OrbitView;
+
+pub mod keys;
+pub use keys::{Action, Keybindings};
+
+/// Loads the [`Settings`], applies them whenever they change and saves them on exit.
+/// Add it after the `DefaultPlugins`, so errors reading the file get logged.
+pub struct SettingsPlugin;
+
+impl Plugin for SettingsPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Settings::load())
+            .add_systems(Update, apply_settings.run_if(resource_changed::<Settings>))
+            .add_systems(Last, save_settings_on_exit);
+    }
+}
+
+/// Samples per pixel the settings offer, 1 turns MSAA off.
+pub const MSAA_SAMPLES: [u32; 4] = [1, 2, 4, 8];
+
+#[derive(Debug, Resource, Clone, PartialEq)]
+pub struct Settings {
+    pub vsync: bool,
+    /// Samples per pixel, one of [`MSAA_SAMPLES`].
+    pub msaa: u32,
+    /// Chunks further away from the camera are hidden.
+    pub render_distance: u32,
+    /// Multiplier for the mouse rotation of the cameras.
+    pub camera_sensitivity: f32,
+    /// Frame time logging and the inspector, only read on startup. Always on in debug builds.
+    pub diagnostics: bool,
+    pub keys: Keybindings,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            vsync: false,
+            msaa: 1,
+            render_distance: 16,
+            camera_sensitivity: 1.0,
+            diagnostics: false,
+            keys: Keybindings::default(),
+        }
+    }
+}
+
+impl Settings {
+    pub fn present_mode(&self) -> PresentMode {
+        match self.vsync {
+            true => PresentMode::AutoVsync,
+            false => PresentMode::Immediate,
+        }
+    }
+
+    pub fn msaa(&self) -> Msaa {
+        match self.msaa {
+            2 => Msaa::Sample2,
+            4 => Msaa::Sample4,
+            8 => Msaa::Sample8,
+            _ => Msaa::Off,
+        }
+    }
+
+    /// Reads the settings file, missing or broken files fall back to the defaults.
+    pub fn load() -> Self {
+        let Some(path) = settings_path() else {
+            warn!("No config directory, using the default settings");
+            return Self::default();
+        };
+        if !path.exists() {
+            return Self::default();
+        }
+        match read_settings(&path) {
+            Ok(settings) => settings,
+            Err(err) => {
+                error!("Failed to read settings {}: {err}", path.display());
+                Self::default()
+            }
+        }
+    }
+}
+
+/// The platform config directory, with a folder for the game.
+pub fn config_dir() -> Option<PathBuf> {
+    let var = |name: &str| {
+        std::env::var_os(name)
+            .filter(|value| !value.is_empty())
+            .map(PathBuf::from)
+    };
+    let base = if cfg!(windows) {
+        var("APPDATA")
+    } else if cfg!(target_os = "macos") {
+        var("HOME").map(|home| home.join("Library/Application Support"))
+    } else {
+        var("XDG_CONFIG_HOME").or_else(|| var("HOME").map(|home| home.join(".config")))
+    };
+    base.map(|dir| dir.join("dwarf_land"))
+}
+
+pub fn settings_path() -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join("settings.cfg"))
+}
+
+pub fn write_settings(settings: &Settings) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "vsync {}", settings.vsync);
+    let _ = writeln!(out, "msaa {}", settings.msaa);
+    let _ = writeln!(out, "render_distance {}", settings.render_distance);
+    let _ = writeln!(out, "camera_sensitivity {}", settings.camera_sensitivity);
+    let _ = writeln!(out, "diagnostics {}", settings.diagnostics);
+    for action in Action::ALL {
+        let _ = writeln!(out, "key {action:?} {:?}", settings.keys.key(action));
+    }
+    out
+}
+
+pub fn read_settings(path: &Path) -> Result<Settings, String> {
+    let text = fs::read_to_string(path).map_err(|err| err.to_string())?;
+    parse_settings(&text)
+}
+
+/// Entries that are missing keep their default.
+pub fn parse_settings(text: &str) -> Result<Settings, String> {
+    let mut settings = Settings::default();
+
+    for (number, line) in text.lines().enumerate() {
+        let parts: Vec<_> = line.split_whitespace().collect();
+        let error = || format!("line {}: invalid entry `{line}`", number + 1);
+
+        match parts.as_slice() {
+            [] => {}
+            ["vsync", value] => settings.vsync = value.parse().map_err(|_| error())?,
+            ["msaa", value] => {
+                settings.msaa = value
+                    .parse()
+                    .ok()
+                    .filter(|samples| MSAA_SAMPLES.contains(samples))
+                    .ok_or_else(error)?
+            }
+            ["render_distance", value] => {
+                settings.render_distance = value.parse().map_err(|_| error())?
+            }
+            ["camera_sensitivity", value] => {
+                settings.camera_sensitivity = value.parse().map_err(|_| error())?
+            }
+            ["diagnostics", value] => settings.diagnostics = value.parse().map_err(|_| error())?,
+            ["key", action, key] => {
+                let action = Action::ALL
+                    .into_iter()
+                    .find(|a| format!("{a:?}") == *action)
+                    .ok_or_else(error)?;
+                let key = keys::parse_key(key).ok_or_else(error)?;
+                settings.keys.set(action, key);
+            }
+            _ => return Err(error()),
+        }
+    }
+
+    Ok(settings)
+}
+
+/// Mouse rotation of the fps camera at a sensitivity of 1.
+const FPS_ROTATE_SENSITIVITY: f32 = 0.2;
+/// Mouse rotation of the orbit camera at a sensitivity of 1, in radians per pixel.
+const ORBIT_ROTATE_SENSITIVITY: f32 = 0.005;
+
+fn apply_settings(
+    settings: Res<Settings>,
+    mut msaa: ResMut<Msaa>,
+    mut windows: Query<&mut Window, With<PrimaryWindow>>,
+    mut fps: Query<&mut FpsCameraController>,
+    mut orbit: ResMut<OrbitView>,
+) {
+    let present_mode = settings.present_mode();
+    for mut window in windows.iter_mut() {
+        if window.present_mode != present_mode {
+            window.present_mode = present_mode;
+        }
+    }
+    if *msaa != settings.msaa() {
+        *msaa = settings.msaa();
+    }
+
+    for mut controller in fps.iter_mut() {
+        controller.mouse_rotate_sensitivity =
+            Vec2::splat(FPS_ROTATE_SENSITIVITY * settings.camera_sensitivity);
+    }
+    orbit.rotate_sensitivity = ORBIT_ROTATE_SENSITIVITY * settings.camera_sensitivity;
+}
+
+fn save_settings_on_exit(exit: EventReader<AppExit>, settings: Res<Settings>) {
+    if exit.is_empty() {
+        return;
+    }
+    let Some(path) = settings_path() else {
+        return;
+    };
+    if let Some(dir) = path.parent() {
+        let _ = fs::create_dir_all(dir);
+    }
+    match fs::write(&path, write_settings(&settings)) {
+        Ok(()) => info!("Saved settings to {}", path.display()),
+        Err(err) => error!("Failed to save settings to {}: {err}", path.display()),
+    }
+}
@@ -0,0 +1,47 @@
+use std::fmt;
+
+pub const TICKS_PER_DAY: u64 = 1200;
+pub const DAYS_PER_SEASON: u64 = 28;
+pub const SEASONS_PER_YEAR: u64 = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Season {
+    Spring,
+    Summer,
+    Autumn,
+    Winter,
+}
+
+/// Point in time in the calendar of the world, counted from the first tick.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Date {
+    /// Starts at 1.
+    pub year: u64,
+    pub season: Season,
+    /// Day of the season, starts at 1.
+    pub day: u64,
+}
+
+impl Date {
+    pub fn from_tick(tick: u64) -> Self {
+        let days = tick / TICKS_PER_DAY;
+        let seasons = days / DAYS_PER_SEASON;
+        let season = match seasons % SEASONS_PER_YEAR {
+            0 => Season::Spring,
+            1 => Season::Summer,
+            2 => Season::Autumn,
+            _ => Season::Winter,
+        };
+        Self {
+            year: seasons / SEASONS_PER_YEAR + 1,
+            season,
+            day: days % DAYS_PER_SEASON + 1,
+        }
+    }
+}
+
+impl fmt::Display for Date {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?} {}, year {}", self.season, self.day, self.year)
+    }
+}
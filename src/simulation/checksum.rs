@@ -0,0 +1,177 @@
+use std::hash::{Hash, Hasher};
+
+use bevy::{ecs::system::SystemParam, prelude::*};
+
+use crate::{
+    buildings::{Blueprint, Building},
+    dwarf_map::{
+        chunk::{checksum::ChunkChecksum, ChunkCache},
+        TilePos,
+    },
+    dwarves::{ai::Activity, LaborPreferences, Needs, Skills},
+    items::{stockpile::Stockpile, Item, Reserved},
+};
+
+/// FNV-1a, unlike the std hashers its output doesn't change between runs and Rust versions.
+#[derive(Debug, Clone, Copy)]
+pub struct StableHasher(u64);
+
+impl Default for StableHasher {
+    fn default() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Hasher for StableHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    // fixed size and byte order, so 32 bit machines agree
+
+    fn write_u16(&mut self, i: u16) {
+        self.write(&i.to_le_bytes())
+    }
+
+    fn write_u32(&mut self, i: u32) {
+        self.write(&i.to_le_bytes())
+    }
+
+    fn write_u64(&mut self, i: u64) {
+        self.write(&i.to_le_bytes())
+    }
+
+    fn write_usize(&mut self, i: usize) {
+        self.write_u64(i as u64)
+    }
+}
+
+pub fn stable_hash(value: &impl Hash) -> u64 {
+    let mut hasher = StableHasher::default();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Combines the hashes of all values, independent of the order they come in.
+pub fn hash_unordered(hashes: impl Iterator<Item = u64>) -> u64 {
+    let mut hashes: Vec<_> = hashes.collect();
+    hashes.sort_unstable();
+    let mut hasher = StableHasher::default();
+    for hash in hashes {
+        hasher.write_u64(hash);
+    }
+    hasher.finish()
+}
+
+/// Checksum of the tiles and of everything the simulation changes. Entity ids are left out,
+/// they depend on what happened outside the simulation, like spawning the meshes.
+#[derive(SystemParam)]
+pub struct SimulationChecksum<'w, 's> {
+    cache: Res<'w, ChunkCache>,
+    chunks: Query<'w, 's, &'static ChunkChecksum>,
+    dwarves: Query<
+        'w,
+        's,
+        (
+            &'static TilePos,
+            &'static Needs,
+            &'static Skills,
+            &'static LaborPreferences,
+            &'static Activity,
+        ),
+    >,
+    items: Query<'w, 's, (&'static Item, &'static TilePos, Has<Reserved>)>,
+    blueprints: Query<'w, 's, &'static Blueprint>,
+    buildings: Query<'w, 's, &'static Building>,
+    stockpiles: Query<'w, 's, &'static Stockpile>,
+}
+
+impl SimulationChecksum<'_, '_> {
+    pub fn compute(&self) -> u64 {
+        let world = self.cache.world_checksum(&self.chunks);
+        let dwarves = hash_unordered(self.dwarves.iter().map(
+            |(pos, needs, skills, labors, activity)| {
+                let activity = match activity {
+                    Activity::Idle => 0u8,
+                    Activity::Building(_) => 1,
+                    Activity::Eating => 2,
+                    Activity::Drinking => 3,
+                    Activity::Sleeping => 4,
+                };
+                let floats = [
+                    needs.hunger,
+                    needs.thirst,
+                    needs.fatigue,
+                    skills.construction,
+                ];
+                stable_hash(&(
+                    pos.0,
+                    floats.map(f32::to_bits),
+                    labors.construction,
+                    activity,
+                ))
+            },
+        ));
+        let items = hash_unordered(self.items.iter().map(|(item, pos, reserved)| {
+            stable_hash(&(item.kind, item.material, pos.0, reserved))
+        }));
+        let blueprints = hash_unordered(self.blueprints.iter().map(|b| {
+            stable_hash(&(
+                b.kind,
+                b.footprint,
+                b.work_left.to_bits(),
+                b.materials.len(),
+                b.worker.is_some(),
+            ))
+        }));
+        let buildings = hash_unordered(
+            self.buildings
+                .iter()
+                .map(|b| stable_hash(&(b.kind, b.footprint, b.material))),
+        );
+        let stockpiles =
+            hash_unordered(self.stockpiles.iter().map(|s| stable_hash(&(s.min, s.max))));
+
+        stable_hash(&(world, dwarves, items, blueprints, buildings, stockpiles))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fnv1a(bytes: &[u8]) -> u64 {
+        let mut hasher = StableHasher::default();
+        hasher.write(bytes);
+        hasher.finish()
+    }
+
+    #[test]
+    fn stable_hasher_is_fnv1a() {
+        // reference values of 64 bit FNV-1a
+        assert_eq!(fnv1a(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(fnv1a(b"a"), 0xaf63_dc4c_8601_ec8c);
+        assert_eq!(fnv1a(b"foobar"), 0x8594_4171_f739_67e8);
+    }
+
+    #[test]
+    fn integers_are_hashed_little_endian() {
+        assert_eq!(stable_hash(&0x6261_u16), fnv1a(b"ab"));
+        assert_eq!(stable_hash(&0x6463_6261_u32), fnv1a(b"abcd"));
+        assert_eq!(stable_hash(&1_usize), stable_hash(&1_u64));
+    }
+
+    #[test]
+    fn unordered_hash_ignores_the_order() {
+        let a = hash_unordered([1, 2, 3].into_iter());
+        assert_eq!(a, hash_unordered([3, 1, 2].into_iter()));
+        assert_ne!(a, hash_unordered([1, 2].into_iter()));
+    }
+}
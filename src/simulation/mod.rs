@@ -0,0 +1,291 @@
+use std::path::PathBuf;
+
+use bevy::{ecs::schedule::ScheduleLabel, prelude::*};
+
+use crate::{
+    dwarf_map::generation::WorldSettings,
+    prelude::*,
+    settings::{Action, Settings},
+};
+
+pub mod calendar;
+pub mod checksum;
+pub mod replay;
+pub mod rng;
+#[cfg(test)]
+mod tests;
+use calendar::Date;
+
+/// Runs the [`Simulation`] schedule on a fixed tick, separate from rendering frames.
+/// Agents, jobs and everything else that changes the world on its own belongs in there instead of `Update`.
+#[derive(Default)]
+pub struct SimulationPlugin {
+    /// Seed of the world, a random one if not set.
+    pub seed: Option<u64>,
+    /// Replay log to play back, its seed wins over `seed`.
+    pub replay: Option<PathBuf>,
+}
+
+impl SimulationPlugin {
+    /// Reads `--seed <seed>` and `--replay <file>` from the command line.
+    pub fn from_args() -> Self {
+        let args: Vec<_> = std::env::args().collect();
+        let value = |name: &str| {
+            args.iter()
+                .position(|arg| arg == name)
+                .and_then(|i| args.get(i + 1))
+        };
+        Self {
+            seed: value("--seed").and_then(|seed| seed.parse().ok()),
+            replay: value("--replay").map(PathBuf::from),
+        }
+    }
+}
+
+impl Plugin for SimulationPlugin {
+    fn build(&self, app: &mut App) {
+        let log = self
+            .replay
+            .as_ref()
+            .and_then(|path| match replay::read_log(path) {
+                Ok(log) => Some(log),
+                Err(err) => {
+                    error!("Failed to read replay {}: {err}", path.display());
+                    None
+                }
+            });
+        // a world from the command line skips the main menu
+        if self.seed.is_some() || log.is_some() {
+            app.insert_resource(SkipMainMenu);
+        }
+        let replay = match log {
+            Some(log) => replay::Replay::play(log),
+            None => replay::Replay::record(WorldSettings {
+                seed: self.seed.unwrap_or_else(rand::random),
+                ..default()
+            }),
+        };
+
+        app.init_schedule(Simulation)
+            .configure_sets(
+                Simulation,
+                (
+                    SimulationSet::Input,
+                    SimulationSet::Update,
+                    SimulationSet::Checksum,
+                )
+                    .chain(),
+            )
+            .insert_resource(rng::SimRng::new(replay.log.world.seed))
+            .insert_resource(replay)
+            .init_resource::<replay::PlayerCommands>()
+            .init_resource::<replay::TickCommands>()
+            .insert_resource(Time::<Fixed>::from_hz(SIMULATION_HZ))
+            .init_state::<SimulationState>()
+            .init_resource::<SimulationClock>()
+            .register_type::<SimulationClock>()
+            .add_systems(
+                FixedUpdate,
+                run_simulation.run_if(in_state(GameState::Playing)),
+            )
+            .add_systems(
+                Simulation,
+                (
+                    (replay::collect_player_commands, replay::apply_tile_commands)
+                        .chain()
+                        .in_set(SimulationSet::Input),
+                    (
+                        crate::dwarf_map::chunk::checksum::update_chunk_checksums,
+                        replay::check_checksum,
+                    )
+                        .chain()
+                        .in_set(SimulationSet::Checksum),
+                ),
+            )
+            .add_systems(OnEnter(GameState::Generating), reset_simulation)
+            .add_systems(OnExit(GameState::Editor), replay::record_editor_changes)
+            .add_systems(
+                Update,
+                catch_up_replay.run_if(in_state(LoadingState::ReplayingSave)),
+            )
+            .add_systems(
+                OnTransition {
+                    from: GameState::Playing,
+                    to: GameState::MainMenu,
+                },
+                replay::save_game,
+            )
+            .add_systems(
+                OnTransition {
+                    from: GameState::Editor,
+                    to: GameState::MainMenu,
+                },
+                replay::save_game,
+            )
+            .add_systems(Last, replay::save_replay_on_exit)
+            .add_systems(
+                Update,
+                simulation_controls.run_if(in_state(GameState::Playing)),
+            );
+    }
+}
+
+/// The world was given on the command line, the game starts without the main menu.
+#[derive(Debug, Resource)]
+pub struct SkipMainMenu;
+
+/// Schedule with all systems that advance the simulation, runs once per tick.
+#[derive(ScheduleLabel, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Simulation;
+
+/// Order of the systems within a tick.
+#[derive(SystemSet, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SimulationSet {
+    /// Player commands are collected or read from the replay.
+    Input,
+    /// Agents, jobs and everything else that advances the world.
+    Update,
+    /// The state at the end of the tick is hashed.
+    Checksum,
+}
+
+/// Ticks per second at [`SimulationSpeed::Normal`].
+pub const SIMULATION_HZ: f64 = 10.0;
+/// Simulated seconds per tick, use this instead of [`Time`] so the result doesn't depend on the speed.
+pub const TICK_SECONDS: f32 = 1.0 / SIMULATION_HZ as f32;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Reflect)]
+pub enum SimulationSpeed {
+    #[default]
+    Normal,
+    Fast,
+    Fastest,
+}
+
+impl SimulationSpeed {
+    /// Ticks per fixed update.
+    pub fn ticks(&self) -> u32 {
+        match self {
+            SimulationSpeed::Normal => 1,
+            SimulationSpeed::Fast => 2,
+            SimulationSpeed::Fastest => 5,
+        }
+    }
+}
+
+/// Not saved, loading a save replays its input log and the tick ends up at
+/// [`InputLog::last_tick`](replay::InputLog::last_tick).
+#[derive(Debug, Resource, Reflect, Default)]
+#[reflect(Resource)]
+pub struct SimulationClock {
+    /// Ticks since the world was created.
+    pub tick: u64,
+    pub speed: SimulationSpeed,
+    /// Runs a single tick while paused.
+    #[reflect(ignore)]
+    step: bool,
+}
+
+impl SimulationClock {
+    pub fn date(&self) -> Date {
+        Date::from_tick(self.tick)
+    }
+}
+
+fn run_simulation(world: &mut World) {
+    let ticks = match world.resource::<State<SimulationState>>().get() {
+        SimulationState::Running => world.resource::<SimulationClock>().speed.ticks(),
+        SimulationState::Paused => {
+            let mut clock = world.resource_mut::<SimulationClock>();
+            std::mem::take(&mut clock.step) as u32
+        }
+    };
+
+    for _ in 0..ticks {
+        tick(world);
+    }
+}
+
+fn tick(world: &mut World) {
+    world.resource_mut::<SimulationClock>().tick += 1;
+    world.run_schedule(Simulation);
+}
+
+/// Starts the world of the current [`Replay`](replay::Replay) from its first tick.
+fn reset_simulation(
+    mut commands: Commands,
+    replay: Res<replay::Replay>,
+    mut next_state: ResMut<NextState<SimulationState>>,
+) {
+    commands.insert_resource(rng::SimRng::new(replay.log.world.seed));
+    commands.insert_resource(SimulationClock::default());
+    commands.insert_resource(replay::PlayerCommands::default());
+    commands.insert_resource(replay::TickCommands::default());
+    next_state.set(SimulationState::Running);
+}
+
+/// Ticks run per frame while a save catches up.
+const CATCH_UP_TICKS: u32 = 500;
+
+/// Runs the ticks of a loaded save without rendering them, the replay stops on its last tick.
+fn catch_up_replay(world: &mut World) {
+    for _ in 0..CATCH_UP_TICKS {
+        if !world.resource::<replay::Replay>().playback {
+            break;
+        }
+        tick(world);
+    }
+    if world.resource::<replay::Replay>().playback {
+        return;
+    }
+    world
+        .resource_mut::<NextState<GameState>>()
+        .set(GameState::Playing);
+    world
+        .resource_mut::<NextState<LoadingState>>()
+        .set(LoadingState::Done);
+}
+
+/// `P` pauses, `.` steps a single tick while paused, `1`, `2` and `3` set the speed.
+/// The keys can be changed in the [`Settings`].
+fn simulation_controls(
+    keys: Res<ButtonInput<KeyCode>>,
+    settings: Res<Settings>,
+    state: Res<State<SimulationState>>,
+    mut next_state: ResMut<NextState<SimulationState>>,
+    mut clock: ResMut<SimulationClock>,
+) {
+    let bindings = &settings.keys;
+    let mut changed = false;
+    if bindings.just_pressed(&keys, Action::Pause) {
+        next_state.set(match state.get() {
+            SimulationState::Running => SimulationState::Paused,
+            SimulationState::Paused => SimulationState::Running,
+        });
+        changed = true;
+    }
+    if bindings.just_pressed(&keys, Action::Step) && *state.get() == SimulationState::Paused {
+        clock.step = true;
+    }
+
+    let speed = [
+        (Action::SpeedNormal, SimulationSpeed::Normal),
+        (Action::SpeedFast, SimulationSpeed::Fast),
+        (Action::SpeedFastest, SimulationSpeed::Fastest),
+    ]
+    .into_iter()
+    .find(|(action, _)| bindings.just_pressed(&keys, *action));
+    if let Some((_, speed)) = speed {
+        clock.speed = speed;
+        changed = true;
+    }
+
+    if changed {
+        info!(
+            "{}, tick {}, speed {:?}",
+            clock.date(),
+            clock.tick,
+            clock.speed
+        );
+    }
+}
@@ -0,0 +1,530 @@
+use std::{
+    fmt::Write as _,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use bevy::{app::AppExit, prelude::*};
+
+use super::{checksum::SimulationChecksum, SimulationClock};
+use crate::{
+    buildings::BuildingKind,
+    dwarf_map::{
+        chunk::{MapCommands, Tile},
+        generation::WorldSettings,
+        history::MapHistory,
+        tile_atlas::TileAtlas,
+        MapError,
+    },
+    prelude::*,
+};
+
+/// Something the player did that changes the simulation.
+/// Input systems queue these in [`PlayerCommands`] instead of touching the world,
+/// the simulation applies them at the start of the next tick and records them in the [`InputLog`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlayerCommand {
+    PlaceBlueprint {
+        kind: BuildingKind,
+        center: UVec3,
+    },
+    DesignateStockpile {
+        a: UVec3,
+        b: UVec3,
+    },
+    /// A tile changed in the editor. The editor writes it right away,
+    /// only playback applies it, at the start of the tick after the edit.
+    SetTile {
+        pos: UVec3,
+        tile: Tile,
+    },
+}
+
+/// Commands waiting for the next tick.
+#[derive(Debug, Resource, Default)]
+pub struct PlayerCommands {
+    pending: Vec<PlayerCommand>,
+}
+
+impl PlayerCommands {
+    pub fn send(&mut self, command: PlayerCommand) {
+        self.pending.push(command);
+    }
+}
+
+/// Commands applied during the current tick.
+#[derive(Debug, Resource, Default, Deref)]
+pub struct TickCommands(Vec<PlayerCommand>);
+
+/// World, commands and checksums of a run, enough to replay it.
+/// Saves are logs as well, loading one replays it up to where it was left.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct InputLog {
+    pub world: WorldSettings,
+    pub commands: Vec<(u64, PlayerCommand)>,
+    pub checksums: Vec<(u64, u64)>,
+}
+
+#[derive(Debug, Resource)]
+pub struct Replay {
+    pub log: InputLog,
+    /// Commands come from the log instead of the player, and checksums are compared instead of recorded.
+    pub playback: bool,
+    /// Checksums of the log that didn't match the simulation during playback.
+    pub desyncs: usize,
+    next_command: usize,
+    next_checksum: usize,
+}
+
+impl Replay {
+    pub fn record(world: WorldSettings) -> Self {
+        Self {
+            log: InputLog { world, ..default() },
+            playback: false,
+            desyncs: 0,
+            next_command: 0,
+            next_checksum: 0,
+        }
+    }
+
+    pub fn play(log: InputLog) -> Self {
+        Self {
+            log,
+            playback: true,
+            desyncs: 0,
+            next_command: 0,
+            next_checksum: 0,
+        }
+    }
+}
+
+impl InputLog {
+    /// The tick the run ended on, a checksum is recorded for every tick.
+    pub fn last_tick(&self) -> u64 {
+        self.checksums.last().map_or(0, |(tick, _)| *tick)
+    }
+}
+
+/// Where the log of the last run is written to on exit.
+pub const LATEST_REPLAY: &str = "replays/latest.replay";
+/// Saves are written here when the game is left for the main menu, one per seed.
+pub const SAVE_DIR: &str = "saves";
+
+pub fn save_path(world: &WorldSettings) -> PathBuf {
+    Path::new(SAVE_DIR).join(format!("world-{}.replay", world.seed))
+}
+
+pub fn collect_player_commands(
+    clock: Res<SimulationClock>,
+    mut pending: ResMut<PlayerCommands>,
+    mut tick_commands: ResMut<TickCommands>,
+    mut replay: ResMut<Replay>,
+) {
+    tick_commands.0.clear();
+    let replay = replay.as_mut();
+
+    if replay.playback {
+        pending.pending.clear();
+        while let Some((tick, command)) = replay.log.commands.get(replay.next_command) {
+            if *tick > clock.tick {
+                break;
+            }
+            tick_commands.0.push(*command);
+            replay.next_command += 1;
+        }
+    } else {
+        for command in pending.pending.drain(..) {
+            replay.log.commands.push((clock.tick, command));
+            tick_commands.0.push(command);
+        }
+    }
+}
+
+/// Moves the tiles changed in the editor into the log, so saves and replays keep them.
+pub fn record_editor_changes(
+    clock: Res<SimulationClock>,
+    mut history: ResMut<MapHistory>,
+    mut replay: ResMut<Replay>,
+) {
+    let changes = history.take_changes();
+    if replay.playback {
+        if !changes.is_empty() {
+            warn!("Editor changes during a replay are not recorded, the replay will desync");
+        }
+        return;
+    }
+    // the edits happened after the current tick, the next one starts with them
+    let tick = clock.tick + 1;
+    replay.log.commands.extend(
+        changes
+            .into_iter()
+            .map(|(pos, tile)| (tick, PlayerCommand::SetTile { pos, tile })),
+    );
+}
+
+pub fn apply_tile_commands(
+    tick_commands: Res<TickCommands>,
+    atlas: Res<TileAtlas>,
+    mut map: MapCommands,
+) {
+    for command in tick_commands.iter() {
+        if let PlayerCommand::SetTile { pos, tile } = *command {
+            // a log from another set of tile textures must not put unmeshable tiles on the map
+            if tile.is_solid() && tile.index() >= atlas.layout.textures.len() {
+                warn!(
+                    "Replaying an editor change: {}",
+                    MapError::InvalidTile(tile.index())
+                );
+                continue;
+            }
+            match map.get_tile(pos) {
+                Ok(mut commands) => commands.set(tile),
+                Err(err) => warn!("Replaying an editor change: {err}"),
+            }
+        }
+    }
+}
+
+pub fn check_checksum(
+    clock: Res<SimulationClock>,
+    checksum: SimulationChecksum,
+    mut replay: ResMut<Replay>,
+    mut next_state: ResMut<NextState<SimulationState>>,
+) {
+    let hash = checksum.compute();
+    let replay = replay.as_mut();
+
+    if !replay.playback {
+        replay.log.checksums.push((clock.tick, hash));
+        return;
+    }
+
+    let Some((tick, expected)) = replay.log.checksums.get(replay.next_checksum).copied() else {
+        info!("Replay finished after {} ticks", clock.tick);
+        replay.playback = false;
+        next_state.set(SimulationState::Paused);
+        return;
+    };
+    if tick != clock.tick {
+        return;
+    }
+    replay.next_checksum += 1;
+    if hash != expected {
+        replay.desyncs += 1;
+        error!("Replay desync at tick {tick}: expected {expected:016x}, got {hash:016x}");
+    }
+}
+
+pub fn save_replay_on_exit(exit: EventReader<AppExit>, replay: Res<Replay>) {
+    if exit.is_empty() || replay.playback {
+        return;
+    }
+    save_log(&replay.log, Path::new(LATEST_REPLAY), "replay");
+}
+
+/// Editor changes are part of the save as [`PlayerCommand::SetTile`], entities placed by prefabs aren't.
+pub fn save_game(replay: Res<Replay>) {
+    if replay.playback {
+        return;
+    }
+    save_log(&replay.log, &save_path(&replay.log.world), "game");
+}
+
+fn save_log(log: &InputLog, path: &Path, what: &str) {
+    if let Some(dir) = path.parent() {
+        let _ = fs::create_dir_all(dir);
+    }
+    match fs::write(path, write_log(log)) {
+        Ok(()) => info!("Saved {what} to {}", path.display()),
+        Err(err) => error!("Failed to save {what} to {}: {err}", path.display()),
+    }
+}
+
+// The log is a plain text file with one entry per line:
+// `seed <seed>`, `world <x> <y> <z> embark <x> <z>` with the size in chunks, `command <tick> blueprint <kind> <x> <y> <z>`,
+// `command <tick> stockpile <x> <y> <z> <x> <y> <z>`, `command <tick> tile <x> <y> <z> solid <material>`,
+// `command <tick> tile <x> <y> <z> empty` and `checksum <tick> <hash>`.
+
+pub fn write_log(log: &InputLog) -> String {
+    let world = &log.world;
+    let mut out = format!("seed {}\n", world.seed);
+    let _ = writeln!(
+        out,
+        "world {} {} {} embark {} {}",
+        world.size.x, world.size.y, world.size.z, world.embark.x, world.embark.y
+    );
+    for (tick, command) in &log.commands {
+        let _ = match command {
+            PlayerCommand::PlaceBlueprint { kind, center } => writeln!(
+                out,
+                "command {tick} blueprint {kind:?} {} {} {}",
+                center.x, center.y, center.z
+            ),
+            PlayerCommand::DesignateStockpile { a, b } => writeln!(
+                out,
+                "command {tick} stockpile {} {} {} {} {} {}",
+                a.x, a.y, a.z, b.x, b.y, b.z
+            ),
+            PlayerCommand::SetTile { pos, tile } => {
+                let tile = match tile.is_solid() {
+                    true => format!("solid {}", tile.index()),
+                    false => "empty".to_string(),
+                };
+                writeln!(
+                    out,
+                    "command {tick} tile {} {} {} {tile}",
+                    pos.x, pos.y, pos.z
+                )
+            }
+        };
+    }
+    for (tick, hash) in &log.checksums {
+        let _ = writeln!(out, "checksum {tick} {hash:016x}");
+    }
+    out
+}
+
+pub fn read_log(path: &Path) -> Result<InputLog, String> {
+    let text = fs::read_to_string(path).map_err(|err| err.to_string())?;
+    parse_log(&text)
+}
+
+pub fn parse_log(text: &str) -> Result<InputLog, String> {
+    let mut log = InputLog::default();
+
+    for (number, line) in text.lines().enumerate() {
+        let parts: Vec<_> = line.split_whitespace().collect();
+        let error = || format!("line {}: invalid entry `{line}`", number + 1);
+        let int = |i: usize| -> Result<u64, String> {
+            parts.get(i).and_then(|p| p.parse().ok()).ok_or_else(error)
+        };
+        let coord = |i: usize| -> Result<u32, String> {
+            parts.get(i).and_then(|p| p.parse().ok()).ok_or_else(error)
+        };
+        let tile = |i: usize| -> Result<UVec3, String> {
+            Ok(UVec3::new(coord(i)?, coord(i + 1)?, coord(i + 2)?))
+        };
+
+        match parts.as_slice() {
+            [] => {}
+            ["seed", _] => log.world.seed = int(1)?,
+            ["world", _, _, _, "embark", _, _] => {
+                let size = tile(1)?;
+                let embark = UVec2::new(coord(5)?, coord(6)?);
+                // a broken save must not make the generation allocate an endless world
+                let fits = size.min_element() > 0 && size.cmple(WorldSettings::MAX_SIZE).all();
+                if !fits || embark.cmpge(size.xz()).any() {
+                    return Err(format!(
+                        "line {}: world {size} with embark {embark} does not fit in {}",
+                        number + 1,
+                        WorldSettings::MAX_SIZE
+                    ));
+                }
+                log.world.size = size;
+                log.world.embark = embark;
+            }
+            ["command", _, "blueprint", kind, _, _, _] => {
+                let kind = BuildingKind::ALL
+                    .into_iter()
+                    .find(|k| format!("{k:?}") == *kind)
+                    .ok_or_else(error)?;
+                let command = PlayerCommand::PlaceBlueprint {
+                    kind,
+                    center: tile(4)?,
+                };
+                log.commands.push((int(1)?, command));
+            }
+            ["command", _, "stockpile", _, _, _, _, _, _] => {
+                let command = PlayerCommand::DesignateStockpile {
+                    a: tile(3)?,
+                    b: tile(6)?,
+                };
+                log.commands.push((int(1)?, command));
+            }
+            ["command", _, "tile", _, _, _, "empty"] => {
+                let command = PlayerCommand::SetTile {
+                    pos: tile(3)?,
+                    tile: Tile::EMPTY,
+                };
+                log.commands.push((int(1)?, command));
+            }
+            ["command", _, "tile", _, _, _, "solid", _] => {
+                let command = PlayerCommand::SetTile {
+                    pos: tile(3)?,
+                    tile: Tile::solid(coord(7)? as usize),
+                };
+                log.commands.push((int(1)?, command));
+            }
+            ["checksum", _, hash] => {
+                let hash = u64::from_str_radix(hash, 16).map_err(|_| error())?;
+                log.checksums.push((int(1)?, hash));
+            }
+            _ => return Err(error()),
+        }
+    }
+
+    Ok(log)
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::RunSystemOnce;
+
+    use super::*;
+    use crate::dwarf_map::chunk::{test_tile, test_world};
+
+    fn log() -> InputLog {
+        InputLog {
+            world: WorldSettings {
+                seed: 42,
+                size: UVec3::new(3, 2, 4),
+                embark: UVec2::new(1, 2),
+            },
+            commands: vec![
+                (
+                    3,
+                    PlayerCommand::PlaceBlueprint {
+                        kind: BuildingKind::Workshop,
+                        center: UVec3::new(4, 8, 5),
+                    },
+                ),
+                (
+                    3,
+                    PlayerCommand::DesignateStockpile {
+                        a: UVec3::new(1, 8, 1),
+                        b: UVec3::new(3, 8, 2),
+                    },
+                ),
+                (
+                    7,
+                    PlayerCommand::SetTile {
+                        pos: UVec3::new(0, 1, 2),
+                        tile: Tile::solid(3),
+                    },
+                ),
+                (
+                    7,
+                    PlayerCommand::SetTile {
+                        pos: UVec3::new(2, 1, 0),
+                        tile: Tile::EMPTY,
+                    },
+                ),
+            ],
+            checksums: vec![(1, 0), (2, 0xdead_beef_0123_4567)],
+        }
+    }
+
+    #[test]
+    fn written_logs_parse_back() {
+        let text = write_log(&log());
+        assert_eq!(parse_log(&text), Ok(log()));
+    }
+
+    #[test]
+    fn entries_are_read_in_the_documented_format() {
+        let text = "seed 7\n\
+                    world 2 1 1 embark 1 0\n\
+                    \n\
+                    command 5 blueprint Wall 1 2 3\n\
+                    command 6 tile 1 2 3 empty\n\
+                    checksum 5 00000000000000ff\n";
+        let log = parse_log(text).unwrap();
+        assert_eq!(log.world.seed, 7);
+        assert_eq!(log.world.embark, UVec2::new(1, 0));
+        assert_eq!(
+            log.commands,
+            [
+                (
+                    5,
+                    PlayerCommand::PlaceBlueprint {
+                        kind: BuildingKind::Wall,
+                        center: UVec3::new(1, 2, 3),
+                    }
+                ),
+                (
+                    6,
+                    PlayerCommand::SetTile {
+                        pos: UVec3::new(1, 2, 3),
+                        tile: Tile::EMPTY,
+                    }
+                ),
+            ]
+        );
+        assert_eq!(log.checksums, [(5, 0xff)]);
+        assert_eq!(parse_log(""), Ok(InputLog::default()));
+    }
+
+    #[test]
+    fn malformed_entries_name_their_line() {
+        for line in [
+            "teleport 1 2 3",
+            "seed",
+            "seed -1",
+            "world 2 1 embark 0 0",
+            "command 1 blueprint Tower 1 2 3",
+            "command 1 blueprint Wall 1 2",
+            "command 1 blueprint Wall 1 2 3 4",
+            "command x blueprint Wall 1 2 3",
+            "command 1 stockpile 1 2 3 4 5",
+            "command 1 stockpile 1 2 3 4 5 6 7",
+            "command 1 tile 4294967296 0 0 empty",
+            "command 1 tile 0 -1 0 empty",
+            "world 4294967298 1 1 embark 0 0",
+            "command 1 tile 1 2 3 solid",
+            "command 1 tile 1 2 3 solid stone",
+            "command 1 tile 1 2 3 lava",
+            "checksum 1 xyz",
+            "checksum 1",
+        ] {
+            let text = format!("seed 1\n{line}\n");
+            assert_eq!(
+                parse_log(&text),
+                Err(format!("line 2: invalid entry `{line}`")),
+                "{line}"
+            );
+        }
+    }
+
+    #[test]
+    fn worlds_outside_the_new_game_limits_are_rejected() {
+        for line in [
+            "world 0 1 1 embark 0 0",
+            "world 100000 100000 100000 embark 0 0",
+            "world 9 1 1 embark 0 0",
+            "world 2 1 2 embark 2 0",
+            "world 2 1 2 embark 0 2",
+        ] {
+            let error = parse_log(line).unwrap_err();
+            assert!(error.starts_with("line 1: world"), "{line}: {error}");
+        }
+        let max = WorldSettings::MAX_SIZE;
+        let line = format!("world {} {} {} embark 7 7", max.x, max.y, max.z);
+        assert_eq!(parse_log(&line).map(|log| log.world.size), Ok(max));
+    }
+
+    #[test]
+    fn editor_changes_outside_the_atlas_are_skipped() {
+        let mut world = test_world([UVec3::ZERO]);
+        world.insert_resource(TileAtlas::for_tests(2));
+        world.run_system_once(|mut map: MapCommands| {
+            map.get_tile(UVec3::new(2, 0, 0))
+                .unwrap()
+                .set(Tile::solid(0));
+        });
+        let set = |x, tile| PlayerCommand::SetTile {
+            pos: UVec3::new(x, 0, 0),
+            tile,
+        };
+        world.insert_resource(TickCommands(vec![
+            set(0, Tile::solid(1)),
+            set(1, Tile::solid(2)),
+            set(2, Tile::EMPTY),
+        ]));
+        world.run_system_once(apply_tile_commands);
+
+        let mut tile = |x| test_tile(&mut world, UVec3::new(x, 0, 0));
+        assert_eq!(tile(0), Tile::solid(1));
+        assert_eq!(tile(1), Tile::EMPTY);
+        assert_eq!(tile(2), Tile::EMPTY);
+    }
+}
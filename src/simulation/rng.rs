@@ -0,0 +1,47 @@
+use bevy::prelude::*;
+use rand::{RngCore, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+/// The only source of randomness for the simulation, seeded once per world.
+/// Anything that affects the world has to draw from here, so a replay with the same seed ends up the same.
+/// ChaCha8 gives the same numbers on every platform and rand version, unlike `StdRng`.
+#[derive(Resource)]
+pub struct SimRng {
+    seed: u64,
+    rng: ChaCha8Rng,
+}
+
+impl SimRng {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            rng: ChaCha8Rng::seed_from_u64(seed),
+        }
+    }
+
+    /// Separate generator for a chunk, so generating chunks in a different order gives the same tiles.
+    pub fn for_chunk(&self, cord: UVec3) -> ChaCha8Rng {
+        let [x, y, z] = cord.to_array().map(u64::from);
+        ChaCha8Rng::seed_from_u64(
+            self.seed ^ (x << 42 | y << 21 | z).wrapping_mul(0x9e37_79b9_7f4a_7c15),
+        )
+    }
+}
+
+impl RngCore for SimRng {
+    fn next_u32(&mut self) -> u32 {
+        self.rng.next_u32()
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.rng.next_u64()
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.rng.fill_bytes(dest)
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.rng.try_fill_bytes(dest)
+    }
+}
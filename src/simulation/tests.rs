@@ -0,0 +1,166 @@
+use bevy::prelude::*;
+
+use super::{
+    replay::{PlayerCommand, PlayerCommands, Replay},
+    rng::SimRng,
+    tick, SimulationPlugin,
+};
+use crate::{
+    buildings::{Building, BuildingKind, BuildingPlugin},
+    dwarf_map::{
+        chunk::{ChunkBundle, ChunkCache, ChunkCord, ChunkData, Tile, CHUNK_SIZE},
+        generation::WorldSettings,
+        history::MapHistory,
+        tile_atlas::TileAtlas,
+    },
+    dwarves::{DwarfBundle, DwarfPlugin},
+    items::{ItemBundle, ItemKind, ItemPlugin},
+    prelude::*,
+};
+
+const SEED: u64 = 0x5eed;
+const TICKS: u64 = 600;
+/// Top layer of the ground, everything above it is open.
+const GROUND: u32 = 7;
+
+fn on_ground(x: u32, z: u32) -> UVec3 {
+    UVec3::new(x, GROUND + 1, z)
+}
+
+/// Commands with the tick they are sent on.
+fn command_log() -> Vec<(u64, PlayerCommand)> {
+    let blueprint = |kind, center| PlayerCommand::PlaceBlueprint { kind, center };
+    vec![
+        (1, blueprint(BuildingKind::Wall, on_ground(4, 4))),
+        (1, blueprint(BuildingKind::Wall, on_ground(5, 4))),
+        (1, blueprint(BuildingKind::Workshop, on_ground(20, 20))),
+        (
+            20,
+            PlayerCommand::DesignateStockpile {
+                a: on_ground(2, 12),
+                b: on_ground(5, 14),
+            },
+        ),
+        (40, blueprint(BuildingKind::Door, on_ground(12, 3))),
+        (40, blueprint(BuildingKind::Wall, on_ground(12, 4))),
+    ]
+}
+
+/// Builds a 2x1x2 chunk world from [`SEED`], runs it through the [`command_log`] and returns it.
+/// `shuffled` spawns the same world with other entity ids and in another query order.
+/// The commands are sent as player input, a `replay` in playback ignores them for its own log.
+fn run(shuffled: bool, replay: Replay) -> App {
+    let mut app = App::new();
+    app.insert_state(GameState::Playing)
+        .add_plugins((
+            SimulationPlugin::default(),
+            ItemPlugin,
+            BuildingPlugin,
+            DwarfPlugin,
+        ))
+        .insert_resource(replay)
+        .insert_resource(SimRng::new(SEED))
+        .init_resource::<ChunkCache>()
+        .init_resource::<MapHistory>()
+        .insert_resource(TileAtlas::for_tests(4));
+    let world = &mut app.world;
+
+    if shuffled {
+        for _ in 0..7 {
+            world.spawn_empty();
+        }
+    }
+
+    let mut cords: Vec<_> = (0..2)
+        .flat_map(|x| (0..2).map(move |z| UVec3::new(x, 0, z)))
+        .collect();
+    let mut items: Vec<_> = (0..12)
+        .map(|i: u32| {
+            let kind = ItemKind::ALL[i as usize % ItemKind::ALL.len()];
+            let pos = on_ground(2 + i * 5 % 26, 6 + i * 3 % 20);
+            (kind, i as usize % 2, pos)
+        })
+        .collect();
+    // identical items on the same tile, only their entity ids tell them apart
+    items.extend(items.clone().into_iter().take(3));
+    let mut dwarves = vec![
+        (0, "Urist", on_ground(8, 8)),
+        (1, "Kogan", on_ground(9, 8)),
+        (2, "Domas", on_ground(8, 9)),
+    ];
+    if shuffled {
+        cords.reverse();
+        items.reverse();
+        dwarves.reverse();
+    }
+
+    for cord in cords {
+        let mut chunk = ChunkData::random(&mut world.resource::<SimRng>().for_chunk(cord));
+        for y in GROUND + 1..CHUNK_SIZE as u32 {
+            for x in 0..CHUNK_SIZE as u32 {
+                for z in 0..CHUNK_SIZE as u32 {
+                    chunk.set_tile_local(UVec3::new(x, y, z), Tile::EMPTY);
+                }
+            }
+        }
+        let entity = world
+            .spawn(ChunkBundle {
+                chunk,
+                cord: ChunkCord(cord),
+                ..default()
+            })
+            .id();
+        world.resource_mut::<ChunkCache>().insert(cord, entity);
+    }
+    for (kind, material, pos) in items {
+        world.spawn(ItemBundle::new(kind, material, pos));
+    }
+    for (id, name, pos) in dwarves {
+        world.spawn(DwarfBundle::new(id, name, pos));
+    }
+
+    let log = command_log();
+    for t in 1..=TICKS {
+        for (_, command) in log.iter().filter(|(tick, _)| *tick == t) {
+            world.resource_mut::<PlayerCommands>().send(*command);
+        }
+        tick(world);
+    }
+    app
+}
+
+#[test]
+fn same_seed_and_commands_give_the_same_checksums() {
+    let record = || {
+        Replay::record(WorldSettings {
+            seed: SEED,
+            ..default()
+        })
+    };
+    let mut first = run(false, record());
+    let second = run(true, record());
+
+    // the comparison only means something if the dwarves got some work done
+    let built = first.world.query::<&Building>().iter(&first.world).count();
+    assert!(built > 0, "nothing was built");
+
+    let first_log = &first.world.resource::<Replay>().log;
+    let second_log = &second.world.resource::<Replay>().log;
+    assert_eq!(first_log.commands, command_log());
+    assert_eq!(first_log.checksums.len(), TICKS as usize);
+    for (first, second) in first_log.checksums.iter().zip(&second_log.checksums) {
+        assert_eq!(first, second, "checksums differ at tick {}", first.0);
+    }
+
+    // playing the log back has to hit every recorded checksum
+    let mut third = run(true, Replay::play(first_log.clone()));
+    // the tick after the last checksum ends the playback
+    tick(&mut third.world);
+    let replay = third.world.resource::<Replay>();
+    assert!(
+        !replay.playback,
+        "the replay did not reach the end of its log"
+    );
+    assert_eq!(replay.desyncs, 0);
+    assert_eq!(&replay.log, first_log);
+}
@@ -3,7 +3,28 @@ use bevy::prelude::*;
 #[derive(Debug, States, Hash, PartialEq, Eq, Clone, Copy)]
 pub enum GameState {
     Loading,
+    MainMenu,
+    /// Seed, size and embark location of a new world.
+    NewGame,
+    /// Picks a save to continue.
+    LoadGame,
+    Settings,
+    /// The world of a new game or a save is set up, see [`LoadingState`] for the phases.
+    Generating,
     Playing,
+    /// Map editing, the world is shown but not simulated.
+    Editor,
+}
+
+/// Run condition for systems that show the world, in game and in the editor.
+pub fn in_world(state: Res<State<GameState>>) -> bool {
+    matches!(state.get(), GameState::Playing | GameState::Editor)
+}
+
+/// Run condition for systems in the [`Simulation`](crate::simulation::Simulation) schedule,
+/// it also runs while a loaded save catches up to where it was left.
+pub fn in_simulation(state: Res<State<GameState>>) -> bool {
+    matches!(state.get(), GameState::Playing | GameState::Generating)
 }
 
 #[derive(Debug, States, Hash, PartialEq, Eq, Clone, Copy)]
@@ -11,4 +32,17 @@ pub enum LoadingState {
     Done,
     LoadingAssets,
     BuildingAtlas,
+    GeneratingChunks,
+    /// The commands of a loaded save are run again, see [`Replay`](crate::simulation::replay::Replay).
+    ReplayingSave,
+    /// Loading can't continue, the reason is in the [`LoadingTracker`](crate::assets::LoadingTracker).
+    Failed,
+}
+
+/// Whether the simulation ticks, rendering and the camera keep running while paused.
+#[derive(Debug, States, Hash, PartialEq, Eq, Clone, Copy, Default)]
+pub enum SimulationState {
+    #[default]
+    Running,
+    Paused,
 }